@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE idempotency(
+    idempotency_key TEXT NOT NULL,
+    request_path TEXT NOT NULL,
+    response_status_code SMALLINT NULL,
+    response_header_names TEXT[] NULL,
+    response_header_values BYTEA[] NULL,
+    response_body BYTEA NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (idempotency_key, request_path)
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE sessions(
+    session_id TEXT NOT NULL,
+    state TEXT NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (session_id)
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Existing tokens get a day from now before they expire.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '1 day';
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE rate_limit_buckets(
+    key TEXT NOT NULL,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at timestamptz NOT NULL,
+    -- Past this point the bucket is full again and can be deleted
+    full_at timestamptz NOT NULL,
+    PRIMARY KEY (key)
+);
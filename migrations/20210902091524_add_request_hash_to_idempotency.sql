@@ -0,0 +1,2 @@
+-- Keys saved before this migration match any request.
+ALTER TABLE idempotency ADD COLUMN request_hash TEXT NULL;
@@ -0,0 +1,313 @@
+use crate::authentication::compute_password_hash;
+use crate::configuration::Settings;
+use crate::domain::{NewSubsciber, SubscriberEmail, SubscriberName};
+use crate::email_templates::EmailTemplates;
+use crate::routes::{
+    confirm_subscriber, delete_tokens, generate_subscription_token, get_subscriber_by_email,
+    mark_subscriber_as_unsubscribed, send_confirmation_email, store_token, ConfirmationLinks,
+    ExistingSubscriber
+};
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+const STATUSES: &[&str] = &["pending_confirmation", "confirmed", "unsubscribed"];
+const PASSWORD_VAR: &str = "ZERO2PROD_ADMIN_PASSWORD";
+
+/// Operator tasks, run against the database of the current `APP_ENVIRONMENT`.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "zero2prod-admin")]
+pub enum Command {
+    /// Apply the pending database migrations.
+    Migrate,
+    /// Create a user who can log in to the admin dashboard.
+    ///
+    /// The password is read from ZERO2PROD_ADMIN_PASSWORD or, if unset, from
+    /// the first line piped to stdin: never from the command line, where it
+    /// would end up in the shell history and in `ps`.
+    CreateUser { username: String },
+    /// List subscribers, oldest first.
+    ListSubscribers {
+        #[structopt(long, possible_values = STATUSES)]
+        status: Option<String>,
+        /// Case-insensitive substring of the email or of the name.
+        #[structopt(long)]
+        search: Option<String>
+    },
+    /// Confirm a subscriber without going through the confirmation link.
+    Confirm { email: String },
+    /// Stop sending issues to a subscriber.
+    Unsubscribe { email: String },
+    /// Send a fresh confirmation link to a pending subscriber, revoking the previous ones.
+    ResendConfirmation { email: String },
+    /// Export subscribers as CSV, to stdout unless `--output` is given.
+    Export {
+        #[structopt(long, possible_values = STATUSES)]
+        status: Option<String>,
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>
+    }
+}
+
+/// The password of a new user: `PASSWORD_VAR` if set, else the first line of `input`.
+fn read_password(input: &mut dyn BufRead) -> Result<Secret<String>, anyhow::Error> {
+    if let Ok(password) = std::env::var(PASSWORD_VAR) {
+        return Ok(Secret::new(password));
+    }
+    let mut line = String::new();
+    input.read_line(&mut line).context("Failed to read the password from stdin.")?;
+    let password = line.trim_end_matches(&['\r', '\n'][..]);
+    if password.is_empty() {
+        anyhow::bail!("No password given: set {} or pipe it to stdin.", PASSWORD_VAR);
+    }
+    Ok(Secret::new(password.to_owned()))
+}
+
+pub struct Admin {
+    configuration: Settings,
+    pool: PgPool
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>
+}
+
+impl Admin {
+    pub fn new(configuration: Settings, pool: PgPool) -> Self {
+        Self { configuration, pool }
+    }
+
+    pub async fn build(configuration: Settings) -> Result<Self, sqlx::Error> {
+        let pool = get_connection_pool(&configuration.database).await?;
+        Ok(Self::new(configuration, pool))
+    }
+
+    /// Run `command`, reading what it needs from `input` and writing what it
+    /// has to report to `out`.
+    pub async fn run(
+        &self,
+        command: Command,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write
+    ) -> Result<(), anyhow::Error> {
+        match command {
+            Command::Migrate => {
+                sqlx::migrate!("./migrations")
+                    .run(&self.pool)
+                    .await
+                    .context("Failed to migrate the database.")?;
+                writeln!(out, "Migrations applied")?;
+            }
+            Command::CreateUser { username } => {
+                let password = read_password(input)?;
+                let user_id = self.create_user(&username, password).await?;
+                writeln!(out, "Created user {} ({})", username, user_id)?;
+            }
+            Command::ListSubscribers { status, search } => {
+                for row in self.subscribers(status.as_deref(), search.as_deref()).await? {
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{}",
+                        row.id,
+                        row.email,
+                        row.name,
+                        row.status,
+                        row.subscribed_at.to_rfc3339()
+                    )?;
+                }
+            }
+            Command::Confirm { email } => {
+                let mut transaction = self.begin().await?;
+                let subscriber = find_subscriber(&mut transaction, &email).await?;
+                let confirmed = confirm_subscriber(&mut transaction, subscriber.id)
+                    .await
+                    .context("Failed to update the subscriber status to `confirmed`.")?;
+                if !confirmed {
+                    anyhow::bail!("{} is {}, not pending_confirmation.", email, subscriber.status);
+                }
+                // The links sent so far are of no use anymore
+                delete_tokens(&mut transaction, subscriber.id)
+                    .await
+                    .context("Failed to delete the confirmation tokens.")?;
+                commit(transaction).await?;
+                writeln!(out, "Confirmed {}", email)?;
+            }
+            Command::Unsubscribe { email } => {
+                let mut transaction = self.begin().await?;
+                let subscriber = find_subscriber(&mut transaction, &email).await?;
+                mark_subscriber_as_unsubscribed(&mut transaction, subscriber.id)
+                    .await
+                    .context("Failed to update the subscriber status to `unsubscribed`.")?;
+                commit(transaction).await?;
+                writeln!(out, "Unsubscribed {}", email)?;
+            }
+            Command::ResendConfirmation { email } => {
+                self.resend_confirmation(&email).await?;
+                writeln!(out, "Sent a new confirmation email to {}", email)?;
+            }
+            Command::Export { status, output } => {
+                let rows = self.subscribers(status.as_deref(), None).await?;
+                match output {
+                    Some(path) => {
+                        let file = std::fs::File::create(&path)
+                            .with_context(|| format!("Failed to create {}", path.display()))?;
+                        write_csv(&rows, file)?;
+                        writeln!(out, "Exported {} subscribers to {}", rows.len(), path.display())?;
+                    }
+                    None => write_csv(&rows, out)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn begin(&self) -> Result<Transaction<'_, Postgres>, anyhow::Error> {
+        self.pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")
+    }
+
+    #[tracing::instrument(name = "Create an admin user", skip(self, password))]
+    async fn create_user(&self, username: &str, password: Secret<String>) -> Result<Uuid, anyhow::Error> {
+        let password_hash = compute_password_hash(password.expose_secret().to_owned())
+            .map_err(|e| anyhow::anyhow!("Failed to hash the password: {}", e))?;
+        let user_id = Uuid::new_v4();
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, password_hash)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (username) DO NOTHING
+            "#,
+            user_id,
+            username,
+            password_hash
+        )
+            .execute(&self.pool)
+            .await
+            .context("Failed to store the new user.")?;
+        if result.rows_affected() == 0 {
+            anyhow::bail!("There is already a user named {}.", username);
+        }
+        Ok(user_id)
+    }
+
+    #[tracing::instrument(name = "List subscribers", skip(self, search))]
+    async fn subscribers(
+        &self,
+        status: Option<&str>,
+        search: Option<&str>
+    ) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+        let pattern = search.map(|s| format!("%{}%", escape_like(s)));
+        sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT id, email, name, status, subscribed_at
+            FROM subscriptions
+            WHERE ($1::text IS NULL OR status = $1)
+            AND ($2::text IS NULL OR email ILIKE $2 OR name ILIKE $2)
+            ORDER BY subscribed_at, email
+            "#,
+            status,
+            pattern
+        )
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to retrieve the subscribers.")
+    }
+
+    async fn resend_confirmation(&self, email: &str) -> Result<(), anyhow::Error> {
+        let mut transaction = self.begin().await?;
+        let subscriber = find_subscriber(&mut transaction, email).await?;
+        if subscriber.status != "pending_confirmation" {
+            anyhow::bail!("{} is {}, not pending_confirmation.", email, subscriber.status);
+        }
+        let new_subscriber = NewSubsciber {
+            email: SubscriberEmail::parse(email.to_owned()).map_err(|e| anyhow::anyhow!("email: {}", e))?,
+            name: SubscriberName::parse(subscriber.name).map_err(|e| anyhow::anyhow!("name: {}", e))?
+        };
+        let subscription_token = generate_subscription_token();
+        delete_tokens(&mut transaction, subscriber.id)
+            .await
+            .context("Failed to delete the previous confirmation tokens.")?;
+        store_token(
+            &mut transaction,
+            subscriber.id,
+            &subscription_token,
+            self.configuration.application.subscription_token_ttl()
+        )
+            .await
+            .context("Failed to store the confirmation token for the subscriber.")?;
+        commit(transaction).await?;
+
+        let email_client = self.configuration.email_client.clone().client();
+        let templates = EmailTemplates::load(&self.configuration.application.templates_directory)
+            .context("Failed to load the email templates.")?;
+        let links = ConfirmationLinks {
+            base_url: &self.configuration.application.base_url,
+            hmac_secret: self.configuration.application.hmac_secret.expose_secret()
+        };
+        send_confirmation_email(
+            &email_client,
+            &templates,
+            new_subscriber,
+            subscriber.id,
+            links,
+            &subscription_token
+        )
+            .await
+            .context("Failed to send a confirmation email.")
+    }
+}
+
+async fn find_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str
+) -> Result<ExistingSubscriber, anyhow::Error> {
+    let parsed = SubscriberEmail::parse(email.to_owned()).map_err(|e| anyhow::anyhow!("email: {}", e))?;
+    get_subscriber_by_email(transaction, &parsed)
+        .await
+        .context("Failed to look up the subscriber.")?
+        .ok_or_else(|| anyhow::anyhow!("There is no subscriber with email {}.", email))
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), anyhow::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction.")
+}
+
+fn write_csv(rows: &[SubscriberRow], out: impl Write) -> Result<(), anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(["id", "email", "name", "status", "subscribed_at"])?;
+    for row in rows {
+        writer.write_record([
+            row.id.to_string(),
+            row.email.clone(),
+            row.name.clone(),
+            row.status.clone(),
+            row.subscribed_at.to_rfc3339()
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Match `%` and `_` literally in an `ILIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,89 @@
+use crate::session_state::SessionManager;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpMessage, HttpResponse};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use uuid::Uuid;
+
+/// The id of the admin owning the current session.
+///
+/// Inserted into the request extensions by `RequireLogin`:
+/// handlers behind it can extract it with `web::ReqData<UserId>`.
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(pub Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Redirect anonymous visitors to `/login`.
+pub struct RequireLogin;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireLogin
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequireLoginMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireLoginMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequireLoginMiddleware<S> {
+    service: Rc<S>
+}
+
+impl<S, B> Service<ServiceRequest> for RequireLoginMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let session_manager = req
+                .app_data::<web::Data<SessionManager>>()
+                .cloned()
+                .ok_or_else(|| {
+                    actix_web::error::ErrorInternalServerError("The session manager is not registered")
+                })?;
+            let user_id = {
+                let http_request = req.parts_mut().0.clone();
+                session_manager
+                    .get_user_id(&http_request)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            };
+            match user_id {
+                Some(user_id) => {
+                    req.extensions_mut().insert(UserId(user_id));
+                    service.call(req).await
+                }
+                None => {
+                    let response = HttpResponse::SeeOther()
+                        .insert_header((LOCATION, "/login"))
+                        .finish();
+                    let e = "The user has not logged in";
+                    Err(InternalError::from_response(e, response).into())
+                }
+            }
+        })
+    }
+}
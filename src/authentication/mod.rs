@@ -0,0 +1,8 @@
+mod middleware;
+mod password;
+
+pub use middleware::{RequireLogin, UserId};
+pub use password::{
+    basic_authentication, change_password, compute_password_hash, get_username,
+    validate_credentials, AdminUser, AuthError, Credentials
+};
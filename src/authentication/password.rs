@@ -0,0 +1,282 @@
+use crate::telemetry::spawn_blocking_with_tracing;
+use actix_web::dev::Payload;
+use actix_web::http::{header, HeaderMap, HeaderValue, StatusCode};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+pub struct Credentials {
+    pub username: String,
+    pub password: String
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    InvalidCredentials(String),
+    UnexpectedError(String)
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::InvalidCredentials(e) => write!(f, "Invalid credentials: {}", e),
+            AuthError::UnexpectedError(e) => write!(f, "Something went wrong: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+            AuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AuthError::InvalidCredentials(_) => {
+                let mut response = HttpResponse::Unauthorized().finish();
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="admin""#)
+                );
+                response
+            }
+            AuthError::UnexpectedError(_) => HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// An operator who presented valid credentials.
+///
+/// Add it as a handler argument to restrict the route to admins.
+#[derive(Debug)]
+pub struct AdminUser {
+    pub user_id: Uuid
+}
+
+impl FromRequest for AdminUser {
+    type Config = ();
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let credentials = basic_authentication(req.headers());
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let credentials = credentials?;
+            let pool = pool.ok_or_else(|| {
+                AuthError::UnexpectedError("The database pool is not registered".into())
+            })?;
+            let user_id = validate_credentials(credentials, &pool).await?;
+            Ok(AdminUser { user_id })
+        })
+    }
+}
+
+/// Extract `username:password` from an `Authorization: Basic` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, AuthError> {
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .ok_or_else(|| AuthError::InvalidCredentials("The 'Authorization' header was missing".into()))?
+        .to_str()
+        .map_err(|_| AuthError::InvalidCredentials("The 'Authorization' header was not a valid UTF8 string.".into()))?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .ok_or_else(|| AuthError::InvalidCredentials("The authorization scheme was not 'Basic'.".into()))?;
+    let decoded_bytes = base64::decode_config(base64encoded_segment, base64::STANDARD)
+        .map_err(|_| AuthError::InvalidCredentials("Failed to base64-decode 'Basic' credentials.".into()))?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .map_err(|_| AuthError::InvalidCredentials("The decoded credential string is not valid UTF8.".into()))?;
+
+    // Split into two segments, using ':' as delimitator
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| AuthError::InvalidCredentials("A username must be provided in 'Basic' auth.".into()))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| AuthError::InvalidCredentials("A password must be provided in 'Basic' auth.".into()))?
+        .to_string();
+
+    Ok(Credentials { username, password })
+}
+
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, pool),
+    fields(username = %credentials.username)
+)]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool
+) -> Result<Uuid, AuthError> {
+    // Verify against a dummy hash when the username is unknown, so that the
+    // response time does not tell an attacker which usernames exist.
+    let mut user_id = None;
+    let mut expected_password_hash = "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+        .to_string();
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+        .await
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to spawn blocking task: {}", e)))??;
+
+    user_id.ok_or_else(|| AuthError::InvalidCredentials("Unknown username.".into()))
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: String,
+    password_candidate: String
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(&expected_password_hash)
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to parse hash in PHC string format: {}", e)))?;
+
+    Argon2::default()
+        .verify_password(password_candidate.as_bytes(), &expected_password_hash)
+        .map_err(|_| AuthError::InvalidCredentials("Invalid password.".into()))
+}
+
+#[tracing::instrument(
+    name = "Get stored credentials",
+    skip(username, pool)
+)]
+async fn get_stored_credentials(
+    username: &str,
+    pool: &PgPool
+) -> Result<Option<(Uuid, String)>, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to perform a query to retrieve stored credentials: {}", e)))?
+        .map(|row| (row.user_id, row.password_hash));
+    Ok(row)
+}
+
+#[tracing::instrument(
+    name = "Get username",
+    skip(pool)
+)]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to perform a query to retrieve a username: {}", e)))?;
+    Ok(row.username)
+}
+
+#[tracing::instrument(
+    name = "Change password",
+    skip(password, pool)
+)]
+pub async fn change_password(
+    user_id: Uuid,
+    password: String,
+    pool: &PgPool
+) -> Result<(), AuthError> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to spawn blocking task: {}", e)))??;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash,
+        user_id
+    )
+        .execute(pool)
+        .await
+        .map_err(|e| AuthError::UnexpectedError(format!("Failed to change user's password in the database: {}", e)))?;
+    Ok(())
+}
+
+/// Hash `password` with Argon2id and a fresh random salt, in PHC string format.
+pub fn compute_password_hash(password: String) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None)
+            .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+    )
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| AuthError::UnexpectedError(e.to_string()))?
+        .to_string();
+    Ok(password_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{basic_authentication, compute_password_hash, verify_password_hash};
+    use actix_web::http::{header, HeaderMap, HeaderValue};
+    use claim::{assert_err, assert_ok};
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn basic_credentials_are_decoded(){
+        let encoded = base64::encode("admin:pass:word");
+        let credentials = basic_authentication(&headers(&format!("Basic {}", encoded))).unwrap();
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password, "pass:word");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected(){
+        assert!(basic_authentication(&headers("Bearer token")).is_err());
+    }
+
+    #[test]
+    fn missing_authorization_header_is_rejected(){
+        assert!(basic_authentication(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn a_password_verifies_against_its_own_hash_only(){
+        let hash = compute_password_hash("correct horse".into()).unwrap();
+        assert_ok!(verify_password_hash(hash.clone(), "correct horse".into()));
+        assert_err!(verify_password_hash(hash, "battery staple".into()));
+    }
+}
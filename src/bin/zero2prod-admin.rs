@@ -0,0 +1,30 @@
+use anyhow::Context;
+use std::io::{BufRead, IsTerminal};
+use structopt::StructOpt;
+use zero2prod::admin_cli::{Admin, Command};
+use zero2prod::configuration::get_configuration;
+use zero2prod::pii::set_pii_mode;
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+#[actix_web::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let command = Command::from_args();
+    let configuration = get_configuration().context("Failed to read configuration file")?;
+
+    // stdout is for the command output
+    let subscriber = get_subscriber("zero2prod-admin".into(), "warn".into(), std::io::stderr, None);
+    init_subscriber(subscriber);
+    set_pii_mode(configuration.telemetry.pii, configuration.telemetry.pii_hash_key.clone());
+
+    let admin = Admin::build(configuration)
+        .await
+        .context("Failed to connect to Postgres")?;
+    // Whatever is typed in a terminal is echoed: secrets must be piped instead
+    let stdin = std::io::stdin();
+    let mut input: Box<dyn BufRead> = if stdin.is_terminal() {
+        Box::new(std::io::empty())
+    } else {
+        Box::new(stdin.lock())
+    };
+    admin.run(command, &mut input, &mut std::io::stdout()).await
+}
@@ -0,0 +1,71 @@
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct CaptchaError(pub String);
+
+impl std::fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CAPTCHA verification failure: {}", self.0)
+    }
+}
+
+impl std::error::Error for CaptchaError {}
+
+/// Checks the response a CAPTCHA widget added to the form.
+#[async_trait::async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// `Ok(false)` if the challenge was not solved; `Err` if we could not tell.
+    async fn verify(&self, response: &str) -> Result<bool, CaptchaError>;
+}
+
+/// hCaptcha and Turnstile speak the same `siteverify` protocol.
+pub struct SiteVerifyCaptcha {
+    http_client: reqwest::Client,
+    verify_url: String,
+    secret_key: Secret<String>
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool
+}
+
+impl SiteVerifyCaptcha {
+    pub fn new(verify_url: String, secret_key: Secret<String>, timeout: Duration) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self { http_client, verify_url, secret_key }
+    }
+}
+
+#[async_trait::async_trait]
+impl CaptchaVerifier for SiteVerifyCaptcha {
+    async fn verify(&self, response: &str) -> Result<bool, CaptchaError> {
+        let outcome: SiteVerifyResponse = self.http_client
+            .post(&self.verify_url)
+            .form(&[("secret", self.secret_key.expose_secret().as_str()), ("response", response)])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| CaptchaError(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| CaptchaError(e.to_string()))?;
+        Ok(outcome.success)
+    }
+}
+
+/// Accepts a single, known response: for tests.
+pub struct StubCaptchaVerifier {
+    pub valid_response: String
+}
+
+#[async_trait::async_trait]
+impl CaptchaVerifier for StubCaptchaVerifier {
+    async fn verify(&self, response: &str) -> Result<bool, CaptchaError> {
+        Ok(response == self.valid_response)
+    }
+}
@@ -0,0 +1,223 @@
+mod captcha;
+
+pub use captcha::{CaptchaError, CaptchaVerifier, SiteVerifyCaptcha, StubCaptchaVerifier};
+
+use crate::configuration::{BotProtectionSettings, CaptchaProvider};
+use crate::domain::FormToken;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BotCheckError {
+    #[error("The honeypot field was filled in.")]
+    Honeypot,
+    #[error("The form token is missing or invalid.")]
+    InvalidFormToken,
+    #[error("The form was submitted too quickly.")]
+    TooFast,
+    #[error("The form has expired, please reload the page.")]
+    ExpiredForm,
+    #[error("The CAPTCHA was not solved.")]
+    CaptchaNotSolved,
+    #[error("Failed to verify the CAPTCHA.")]
+    Captcha(#[source] CaptchaError)
+}
+
+/// What the subscribe form sends besides the subscriber details.
+pub struct Submission<'a> {
+    /// Hidden from humans: only bots fill it in.
+    pub honeypot: &'a str,
+    pub form_token: Option<&'a str>,
+    /// The CSRF cookie sent along: the form token must have been issued for it.
+    pub csrf_token: Option<&'a str>,
+    pub captcha_response: Option<&'a str>
+}
+
+/// What the subscribe page needs to render the CAPTCHA widget.
+#[derive(Clone, Debug)]
+pub struct CaptchaWidget {
+    pub provider: CaptchaProvider,
+    pub site_key: String
+}
+
+/// Anti-bot checks on the public subscribe form: a honeypot field, a minimum
+/// time between serving the form and submitting it and, optionally, a CAPTCHA.
+pub struct BotProtection {
+    enabled: bool,
+    min_submit_time: Duration,
+    max_form_age: Duration,
+    hmac_secret: Secret<String>,
+    captcha_widget: Option<CaptchaWidget>,
+    captcha: Option<Arc<dyn CaptchaVerifier>>
+}
+
+impl BotProtection {
+    pub fn new(settings: &BotProtectionSettings, hmac_secret: Secret<String>) -> Self {
+        let captcha: Option<Arc<dyn CaptchaVerifier>> = settings.captcha.as_ref().map(|c| {
+            Arc::new(SiteVerifyCaptcha::new(c.verify_url(), c.secret_key.clone(), c.timeout())) as _
+        });
+        Self {
+            enabled: settings.enabled,
+            min_submit_time: Duration::from_secs(settings.min_submit_seconds),
+            max_form_age: Duration::from_secs(settings.max_form_age_hours * 60 * 60),
+            hmac_secret,
+            captcha_widget: settings.captcha.as_ref().map(|c| CaptchaWidget {
+                provider: c.provider,
+                site_key: c.site_key.clone()
+            }),
+            captcha
+        }
+    }
+
+    /// Verify CAPTCHA responses with `verifier` instead.
+    pub fn with_captcha(mut self, widget: CaptchaWidget, verifier: impl CaptchaVerifier + 'static) -> Self {
+        self.captcha_widget = Some(widget);
+        self.captcha = Some(Arc::new(verifier));
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The CAPTCHA widget to add to the form, if one is configured.
+    pub fn captcha_widget(&self) -> Option<&CaptchaWidget> {
+        self.captcha_widget.as_ref()
+    }
+
+    /// A token to embed in the subscribe form when serving it to the browser
+    /// holding `csrf_token`.
+    pub fn issue_form_token(&self, csrf_token: &str) -> FormToken {
+        FormToken::sign(Utc::now(), csrf_token, self.hmac_secret.expose_secret())
+    }
+
+    /// Pass if the submission looks like it comes from a human.
+    pub async fn check(&self, submission: &Submission<'_>) -> Result<(), BotCheckError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !submission.honeypot.is_empty() {
+            return Err(BotCheckError::Honeypot);
+        }
+        let issued_at = submission.form_token
+            .zip(submission.csrf_token)
+            .and_then(|(token, csrf_token)| {
+                FormToken::verify(token, csrf_token, self.hmac_secret.expose_secret()).ok()
+            })
+            .ok_or(BotCheckError::InvalidFormToken)?;
+        self.check_submit_time(issued_at, Utc::now())?;
+        self.check_captcha(submission.captcha_response).await
+    }
+
+    /// Pass if the CAPTCHA, when one is configured, was solved.
+    ///
+    /// All that can be asked of API clients: they never load the form, so
+    /// they have neither a honeypot nor a form token.
+    pub async fn check_captcha(&self, captcha_response: Option<&str>) -> Result<(), BotCheckError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(captcha) = &self.captcha {
+            let response = captcha_response.unwrap_or_default();
+            if response.is_empty() || !captcha.verify(response).await.map_err(BotCheckError::Captcha)? {
+                return Err(BotCheckError::CaptchaNotSolved);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_submit_time(&self, issued_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<(), BotCheckError> {
+        let elapsed = now - issued_at;
+        if elapsed < chrono::Duration::from_std(self.min_submit_time).unwrap_or_else(|_| chrono::Duration::max_value()) {
+            return Err(BotCheckError::TooFast);
+        }
+        if elapsed > chrono::Duration::from_std(self.max_form_age).unwrap_or_else(|_| chrono::Duration::max_value()) {
+            return Err(BotCheckError::ExpiredForm);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot_protection::{BotCheckError, BotProtection, CaptchaWidget, StubCaptchaVerifier, Submission};
+    use crate::configuration::{BotProtectionSettings, CaptchaProvider};
+    use crate::domain::FormToken;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn bot_protection() -> BotProtection {
+        let settings = BotProtectionSettings {
+            enabled: true,
+            min_submit_seconds: 3,
+            max_form_age_hours: 24,
+            captcha: None
+        };
+        BotProtection::new(&settings, Secret::new("secret".into()))
+    }
+
+    fn token_issued_seconds_ago(seconds: i64) -> String {
+        FormToken::sign(Utc::now() - Duration::seconds(seconds), "cookie", "secret").as_ref().to_owned()
+    }
+
+    fn submission<'a>(form_token: &'a str, captcha_response: Option<&'a str>) -> Submission<'a> {
+        Submission { honeypot: "", form_token: Some(form_token), csrf_token: Some("cookie"), captcha_response }
+    }
+
+    #[actix_rt::test]
+    async fn a_human_paced_submission_passes(){
+        let token = token_issued_seconds_ago(10);
+        assert_ok!(bot_protection().check(&submission(&token, None)).await);
+    }
+
+    #[actix_rt::test]
+    async fn a_filled_in_honeypot_is_caught(){
+        let token = token_issued_seconds_ago(10);
+        let submission = Submission { honeypot: "https://spam.example", ..submission(&token, None) };
+        let outcome = bot_protection().check(&submission).await;
+        assert!(matches!(outcome, Err(BotCheckError::Honeypot)));
+    }
+
+    #[actix_rt::test]
+    async fn submissions_need_a_fresh_enough_but_not_too_fresh_token(){
+        let protection = bot_protection();
+        let outcome = protection.check(&submission(&token_issued_seconds_ago(1), None)).await;
+        assert!(matches!(outcome, Err(BotCheckError::TooFast)));
+        let outcome = protection.check(&submission(&token_issued_seconds_ago(25 * 60 * 60), None)).await;
+        assert!(matches!(outcome, Err(BotCheckError::ExpiredForm)));
+        let outcome = protection.check(&Submission { form_token: None, ..submission("", None) }).await;
+        assert!(matches!(outcome, Err(BotCheckError::InvalidFormToken)));
+        let token = token_issued_seconds_ago(10);
+        let outcome = protection.check(&Submission { csrf_token: Some("another cookie"), ..submission(&token, None) }).await;
+        assert!(matches!(outcome, Err(BotCheckError::InvalidFormToken)));
+    }
+
+    #[actix_rt::test]
+    async fn the_captcha_must_be_solved_when_configured(){
+        let protection = bot_protection()
+            .with_captcha(
+                CaptchaWidget { provider: CaptchaProvider::Turnstile, site_key: "site-key".into() },
+                StubCaptchaVerifier { valid_response: "solved".into() }
+            );
+        let token = token_issued_seconds_ago(10);
+        assert_ok!(protection.check(&submission(&token, Some("solved"))).await);
+        assert_err!(protection.check(&submission(&token, Some("guessed"))).await);
+        assert_err!(protection.check(&submission(&token, None)).await);
+    }
+
+    #[actix_rt::test]
+    async fn nothing_is_checked_when_disabled(){
+        let settings = BotProtectionSettings {
+            enabled: false,
+            min_submit_seconds: 3,
+            max_form_age_hours: 24,
+            captcha: None
+        };
+        let protection = BotProtection::new(&settings, Secret::new("secret".into()));
+        let submission = Submission { honeypot: "spam", form_token: None, csrf_token: None, captcha_response: None };
+        assert_ok!(protection.check(&submission).await);
+    }
+}
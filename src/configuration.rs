@@ -1,14 +1,106 @@
 use std::convert::{TryFrom, TryInto};
+use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::postgres::PgSslMode;
+use opentelemetry::sdk::trace::Tracer;
+use opentelemetry::trace::TraceError;
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailClient, FileTransport, PostmarkTransport, RetryPolicy, SendGridTransport, SmtpTls, SmtpTransport
+};
+use crate::pii::PiiMode;
+use crate::rate_limit::Limit;
+use crate::telemetry::otlp_tracer;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings{
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
-    pub email_client: EmailClientSettings
+    pub email_client: EmailClientSettings,
+    pub telemetry: TelemetrySettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings
+}
+
+/// Longest lifetime accepted for a confirmation link: a year.
+pub const MAX_SUBSCRIPTION_TOKEN_TTL_HOURS: u64 = 365 * 24;
+/// Longest time pending subscribers are kept for: ten years.
+pub const MAX_UNCONFIRMED_SUBSCRIBER_RETENTION_DAYS: u64 = 10 * 365;
+/// Longest time responses are kept for replay: a year.
+pub const MAX_IDEMPOTENCY_KEY_RETENTION_HOURS: u64 = 365 * 24;
+
+impl Settings {
+    /// Reject the values the application cannot work with, rather than
+    /// failing on them at runtime.
+    pub fn validate(self) -> Result<Self, config::ConfigError> {
+        check_duration(
+            "application.subscription_token_ttl_hours",
+            self.application.subscription_token_ttl_hours,
+            60 * 60,
+            MAX_SUBSCRIPTION_TOKEN_TTL_HOURS
+        )?;
+        check_duration(
+            "application.unconfirmed_subscriber_retention_days",
+            self.application.unconfirmed_subscriber_retention_days,
+            24 * 60 * 60,
+            MAX_UNCONFIRMED_SUBSCRIBER_RETENTION_DAYS
+        )?;
+        check_duration(
+            "application.idempotency_key_retention_hours",
+            self.application.idempotency_key_retention_hours,
+            60 * 60,
+            MAX_IDEMPOTENCY_KEY_RETENTION_HOURS
+        )?;
+        if self.telemetry.pii == PiiMode::Hashed && self.telemetry.pii_hash_key.is_none() {
+            return Err(config::ConfigError::Message(
+                "telemetry.pii_hash_key must be set to hash personal data.".into()
+            ));
+        }
+        if matches!(&self.telemetry.metrics_token, Some(token) if token.expose_secret().is_empty()) {
+            return Err(config::ConfigError::Message(
+                "telemetry.metrics_token cannot be empty.".into()
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Check that `value`, counted in units of `unit_seconds`, is between 1 and `max`
+/// and converts to seconds without overflowing.
+fn check_duration(setting: &str, value: u64, unit_seconds: u64, max: u64) -> Result<(), config::ConfigError> {
+    if value == 0 || value > max || value.checked_mul(unit_seconds).is_none() {
+        return Err(config::ConfigError::Message(format!(
+            "{} must be between 1 and {}, got {}.",
+            setting,
+            max,
+            value
+        )));
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TelemetrySettings {
+    pub service_name: String,
+    /// How subscriber emails and names show up in logs and spans.
+    pub pii: PiiMode,
+    /// Keys the hashes of `PiiMode::Hashed`, which requires it.
+    pub pii_hash_key: Option<Secret<String>>,
+    /// Base URL of an OTLP/HTTP collector, e.g. `http://localhost:4318`.
+    /// Spans are only exported when it is set.
+    pub otlp_endpoint: Option<String>,
+    /// Bearer token to scrape `/metrics` with. `/metrics` is not served without it.
+    pub metrics_token: Option<Secret<String>>
+}
+
+impl TelemetrySettings {
+    pub fn tracer(&self) -> Result<Option<Tracer>, TraceError> {
+        self.otlp_endpoint
+            .as_deref()
+            .map(|endpoint| otlp_tracer(self.service_name.clone(), endpoint))
+            .transpose()
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -16,9 +108,144 @@ pub struct ApplicationSettings{
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
-    pub base_url: String
+    pub base_url: String,
+    pub session_store: SessionStoreKind,
+    /// Key used to sign unsubscribe links.
+    pub hmac_secret: Secret<String>,
+    /// Email layouts, partials and bodies, see `EmailTemplates`.
+    pub templates_directory: String,
+    /// How long a confirmation link stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: u64,
+    /// Pending subscribers who never confirmed are deleted after this many days.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub unconfirmed_subscriber_retention_days: u64,
+    /// Responses saved for an `Idempotency-Key` are deleted after this many hours.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idempotency_key_retention_hours: u64,
+    /// On SIGTERM/SIGINT, how long in-flight requests and background workers
+    /// get to finish before being cut off.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_grace_period_seconds: u64
+}
+
+impl ApplicationSettings {
+    pub fn subscription_token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.subscription_token_ttl_hours * 60 * 60)
+    }
+
+    pub fn unconfirmed_subscriber_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.unconfirmed_subscriber_retention_days.saturating_mul(24 * 60 * 60))
+    }
+
+    pub fn idempotency_key_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.idempotency_key_retention_hours.saturating_mul(60 * 60))
+    }
+
+    pub fn shutdown_grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_grace_period_seconds)
+    }
 }
 
+/// Backend holding admin sessions.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Postgres,
+    /// Not shared across replicas: only for tests and local development.
+    Memory
+}
+
+/// Limits on the subscription endpoints, see `RateLimit`.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    /// Identify clients by the `Forwarded`/`X-Forwarded-For` headers rather than
+    /// by the peer address: only safe behind a proxy that sets them.
+    pub use_forwarded_headers: bool,
+    pub per_ip: BucketSettings,
+    pub per_email: BucketSettings
+}
+
+/// A token bucket: `capacity` requests in a burst, then one every `refill_interval_seconds`.
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct BucketSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refill_interval_seconds: u64
+}
+
+impl BucketSettings {
+    pub fn limit(&self) -> Limit {
+        Limit {
+            capacity: self.capacity,
+            refill_interval: std::time::Duration::from_secs(self.refill_interval_seconds)
+        }
+    }
+}
+
+/// Backend holding the rate limit buckets.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    /// Shared by every replica.
+    Postgres,
+    /// Each replica enforces the limits on its own.
+    Memory
+}
+/// Anti-bot checks on the subscribe form, see `BotProtection`.
+#[derive(serde::Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    pub enabled: bool,
+    /// Humans need at least this long to fill in the form.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_seconds: u64,
+    /// Forms served longer ago than this must be reloaded.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_form_age_hours: u64,
+    /// No CAPTCHA unless set.
+    pub captcha: Option<CaptchaSettings>
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CaptchaSettings {
+    pub provider: CaptchaProvider,
+    pub site_key: String,
+    pub secret_key: Secret<String>,
+    /// Overrides the provider's `siteverify` endpoint, e.g. in tests.
+    pub verify_url: Option<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64
+}
+
+impl CaptchaSettings {
+    pub fn verify_url(&self) -> String {
+        self.verify_url
+            .clone()
+            .unwrap_or_else(|| self.provider.verify_url().into())
+    }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptchaProvider {
+    HCaptcha,
+    Turnstile
+}
+
+impl CaptchaProvider {
+    pub fn verify_url(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://hcaptcha.com/siteverify",
+            CaptchaProvider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+        }
+    }
+}
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let mut settings = config::Config::default();
@@ -34,7 +261,7 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
 
     settings.merge(config::File::from(configuration_directory.join(environment.as_str())).required(true))?;
     settings.merge(config::Environment::with_prefix("app").separator("__"))?;
-    settings.try_into()
+    settings.try_into::<Settings>()?.validate()
 }
 
 pub enum Environment{
@@ -67,7 +294,7 @@ impl TryFrom<String> for Environment {
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
-    pub password: String,
+    pub password: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
@@ -85,7 +312,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password)
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -97,12 +324,160 @@ impl DatabaseSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings{
+    pub transport: EmailTransportKind,
+    /// API base URL, for the HTTP transports.
     pub base_url: String,
     pub sender_email: String,
-    pub authorization_token: String
+    pub authorization_token: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_backoff_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_backoff_milliseconds: u64,
+    /// Ping the provider from `/ready`: the instance is taken out of rotation while it is unreachable.
+    pub readiness_check: bool,
+    /// Required when `transport` is `smtp`.
+    pub smtp: Option<SmtpSettings>,
+    /// Where `.eml` files are written when `transport` is `file`.
+    pub output_directory: Option<String>
+}
+
+/// How emails leave the application.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    SendGrid,
+    Postmark,
+    Smtp,
+    /// Write messages to disk instead of sending them: only for local development.
+    File
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>
 }
+
 impl EmailClientSettings {
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
-}
\ No newline at end of file
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            min_backoff: std::time::Duration::from_millis(self.min_backoff_milliseconds),
+            max_backoff: std::time::Duration::from_millis(self.max_backoff_milliseconds)
+        }
+    }
+
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address");
+        let timeout = self.timeout();
+        match self.transport {
+            EmailTransportKind::SendGrid => EmailClient::new(
+                sender_email,
+                SendGridTransport::new(self.base_url, self.authorization_token, timeout)
+            ),
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout)
+            ),
+            EmailTransportKind::Smtp => {
+                let smtp = self.smtp.expect("Missing SMTP settings for the smtp email transport");
+                let credentials = smtp.username.zip(smtp.password);
+                let transport = SmtpTransport::new(&smtp.host, smtp.port, smtp.tls, credentials, timeout)
+                    .expect("Invalid SMTP settings");
+                EmailClient::new(sender_email, transport)
+            }
+            EmailTransportKind::File => {
+                let directory = self.output_directory
+                    .expect("Missing output directory for the file email transport");
+                EmailClient::new(sender_email, FileTransport::new(directory))
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{
+        get_configuration, MAX_IDEMPOTENCY_KEY_RETENTION_HOURS, MAX_SUBSCRIPTION_TOKEN_TTL_HOURS,
+        MAX_UNCONFIRMED_SUBSCRIBER_RETENTION_DAYS
+    };
+    use crate::pii::PiiMode;
+    use secrecy::Secret;
+
+    #[test]
+    fn the_subscription_token_ttl_must_stay_in_range(){
+        let settings = get_configuration().expect("Failed to read configuration.");
+        for (hours, valid) in [(0, false), (48, true), (MAX_SUBSCRIPTION_TOKEN_TTL_HOURS, true), (u64::MAX, false)] {
+            let mut settings = settings.clone();
+            settings.application.subscription_token_ttl_hours = hours;
+            assert_eq!(settings.validate().is_ok(), valid, "TTL of {} hours", hours);
+        }
+    }
+
+    #[test]
+    fn the_unconfirmed_subscriber_retention_must_stay_in_range(){
+        let settings = get_configuration().expect("Failed to read configuration.");
+        let test_cases = [
+            (0, false),
+            (30, true),
+            (MAX_UNCONFIRMED_SUBSCRIBER_RETENTION_DAYS, true),
+            (MAX_UNCONFIRMED_SUBSCRIBER_RETENTION_DAYS + 1, false),
+            (u64::MAX, false)
+        ];
+        for (days, valid) in test_cases {
+            let mut settings = settings.clone();
+            settings.application.unconfirmed_subscriber_retention_days = days;
+            assert_eq!(settings.validate().is_ok(), valid, "Retention of {} days", days);
+        }
+    }
+
+    #[test]
+    fn the_idempotency_key_retention_must_stay_in_range(){
+        let settings = get_configuration().expect("Failed to read configuration.");
+        let test_cases = [
+            (0, false),
+            (48, true),
+            (MAX_IDEMPOTENCY_KEY_RETENTION_HOURS, true),
+            (u64::MAX, false)
+        ];
+        for (hours, valid) in test_cases {
+            let mut settings = settings.clone();
+            settings.application.idempotency_key_retention_hours = hours;
+            assert_eq!(settings.validate().is_ok(), valid, "Retention of {} hours", hours);
+        }
+    }
+
+    #[test]
+    fn hashing_personal_data_requires_a_key(){
+        let mut settings = get_configuration().expect("Failed to read configuration.");
+        settings.telemetry.pii = PiiMode::Hashed;
+        settings.telemetry.pii_hash_key = None;
+        assert!(settings.clone().validate().is_err());
+        settings.telemetry.pii_hash_key = Some(Secret::new("pii-hash-key".into()));
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn the_metrics_token_cannot_be_empty(){
+        let mut settings = get_configuration().expect("Failed to read configuration.");
+        settings.telemetry.metrics_token = Some(Secret::new("".into()));
+        assert!(settings.clone().validate().is_err());
+        settings.telemetry.metrics_token = Some(Secret::new("metrics-token".into()));
+        assert!(settings.validate().is_ok());
+    }
+}
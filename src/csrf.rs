@@ -0,0 +1,90 @@
+//! Cross-site request forgery protection for our public HTML forms.
+//!
+//! Double-submit cookie: the page embeds the value of a `SameSite=Strict`
+//! cookie in a hidden field, and the submission must carry both. Another
+//! site can make a browser post the form, but can neither read the cookie
+//! nor have the browser send it along.
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::HttpRequest;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+const CSRF_COOKIE: &str = "_csrf";
+const TOKEN_LENGTH: usize = 32;
+
+/// The token to embed in a form, and the cookie to set alongside it.
+///
+/// The token already held by the browser is reused, so that a form left
+/// open in another tab keeps working.
+pub fn csrf_token(request: &HttpRequest) -> (String, Cookie<'static>) {
+    let token = read_csrf_cookie(request)
+        .unwrap_or_else(|| {
+            thread_rng()
+                .sample_iter(&Alphanumeric)
+                .map(char::from)
+                .take(TOKEN_LENGTH)
+                .collect()
+        });
+    let cookie = Cookie::build(CSRF_COOKIE, token.clone())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    (token, cookie)
+}
+
+/// Whether `submitted` matches the token in the request's cookie.
+pub fn verify_csrf_token(request: &HttpRequest, submitted: &str) -> bool {
+    match read_csrf_cookie(request) {
+        Some(token) => constant_time_eq(&token, submitted),
+        None => false
+    }
+}
+
+/// The token in the request's cookie, if it holds a well-formed one.
+pub fn read_csrf_cookie(request: &HttpRequest) -> Option<String> {
+    request
+        .cookie(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|token| is_well_formed(token))
+}
+
+fn is_well_formed(token: &str) -> bool {
+    token.len() == TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csrf_token, verify_csrf_token, CSRF_COOKIE};
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn a_token_verifies_against_its_own_cookie(){
+        let (token, cookie) = csrf_token(&TestRequest::default().to_http_request());
+        let request = TestRequest::default().cookie(cookie).to_http_request();
+        assert!(verify_csrf_token(&request, &token));
+        let (other_token, _) = csrf_token(&TestRequest::default().to_http_request());
+        assert!(!verify_csrf_token(&request, &other_token));
+    }
+
+    #[test]
+    fn the_browser_token_is_reused(){
+        let (token, cookie) = csrf_token(&TestRequest::default().to_http_request());
+        let (reused, _) = csrf_token(&TestRequest::default().cookie(cookie).to_http_request());
+        assert_eq!(token, reused);
+    }
+
+    #[test]
+    fn submissions_without_the_cookie_are_rejected(){
+        let (token, _) = csrf_token(&TestRequest::default().to_http_request());
+        assert!(!verify_csrf_token(&TestRequest::default().to_http_request(), &token));
+        // Nor can an empty cookie be matched with an empty field
+        let request = TestRequest::default().cookie(Cookie::new(CSRF_COOKIE, "")).to_http_request();
+        assert!(!verify_csrf_token(&request, ""));
+    }
+}
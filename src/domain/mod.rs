@@ -1,7 +1,13 @@
 mod subscriber_email;
 mod subscriber_name;
 mod new_subscriber;
+mod unsubscribe_token;
+mod form_token;
+mod subscription_id;
 
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
-pub use new_subscriber::NewSubsciber;
\ No newline at end of file
+pub use new_subscriber::NewSubsciber;
+pub use unsubscribe_token::UnsubscribeToken;
+pub use form_token::FormToken;
+pub use subscription_id::SubscriptionId;
@@ -1,6 +1,6 @@
+use crate::pii::Pii;
 use validator::validate_email;
 
-#[derive(Debug)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
@@ -8,7 +8,8 @@ impl SubscriberEmail {
         if validate_email(&s){
             Ok(Self(s))
         } else{
-            Err(format!("{} is not a valid subscriber email.", s))
+            // Never echo the input: errors end up in logs
+            Err("must be a valid email address.".into())
         }
 
     }
@@ -20,6 +21,13 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+/// Subscriber emails end up in spans whenever a struct holding one is recorded.
+impl std::fmt::Debug for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SubscriberEmail({})", Pii(&self.0))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
@@ -9,7 +9,10 @@ pub struct SubscriberName(String);
 // when we use SubscriberName all invariants are guaranteed
 impl SubscriberName {
     pub fn parse(s:String) -> Result<Self, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
+        // Never echo the input in the errors: they end up in logs
+        if s.trim().is_empty() {
+            return Err("cannot be empty.".into());
+        }
 
         // A grapheme is defined by the Unicode standard as a "user-perceived"
         // character: `å`is a single grapheme, but it is composed of two characters
@@ -18,15 +21,14 @@ impl SubscriberName {
         // `graphemes`returns an iterator over the graphemes in the input `s`.
         // `true`specifies that we want to use the extended grapheme definition set,
         // the recommended one.
-        let is_too_long =s.graphemes(true).count() > 256;
+        if s.graphemes(true).count() > 256 {
+            return Err("cannot be longer than 256 characters.".into());
+        }
         let forbidden_characters =['/','(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
-
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not valid subscriber name", s))
-        } else {
-            Ok(Self(s))
+        if s.chars().any(|g| forbidden_characters.contains(&g)) {
+            return Err("cannot contain any of / ( ) \" < > \\ { }.".into());
         }
+        Ok(Self(s))
     }
 
 }
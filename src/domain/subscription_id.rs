@@ -0,0 +1,61 @@
+use crate::domain::SubscriberEmail;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use uuid::{Builder, Uuid, Variant, Version};
+
+/// The id returned by `POST /api/v1/subscriptions`.
+///
+/// Derived from the email with an HMAC rather than read from the database:
+/// new, pending and confirmed subscribers all get one, the same one for
+/// every request with their email, so it does not tell who is already on
+/// the list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubscriptionId(Uuid);
+
+impl SubscriptionId {
+    pub fn for_email(email: &SubscriberEmail, secret: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC can take a key of any size");
+        // The secret also signs unsubscribe and form tokens
+        mac.update(b"subscription id:");
+        mac.update(email.as_ref().as_bytes());
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+        let id = Builder::from_bytes(bytes)
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random)
+            .build();
+        Self(id)
+    }
+}
+
+impl AsRef<Uuid> for SubscriptionId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionId;
+    use crate::domain::SubscriberEmail;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.into()).unwrap()
+    }
+
+    #[test]
+    fn the_same_email_always_gets_the_same_id(){
+        assert_eq!(
+            SubscriptionId::for_email(&email("ursula@example.com"), "secret"),
+            SubscriptionId::for_email(&email("ursula@example.com"), "secret")
+        );
+    }
+
+    #[test]
+    fn ids_depend_on_the_email_and_the_secret(){
+        let id = SubscriptionId::for_email(&email("ursula@example.com"), "secret");
+        assert_ne!(id, SubscriptionId::for_email(&email("atul@example.com"), "secret"));
+        assert_ne!(id, SubscriptionId::for_email(&email("ursula@example.com"), "another secret"));
+    }
+}
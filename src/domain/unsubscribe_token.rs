@@ -0,0 +1,81 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// `<subscriber id>.<HMAC-SHA256 of the id>`, embedded in unsubscribe links.
+///
+/// The signature lets us honour one-click unsubscribes without storing a
+/// token per subscriber, while stopping anyone from unsubscribing others.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn sign(subscriber_id: Uuid, secret: &str) -> Self {
+        let signature = base64::encode_config(
+            mac(subscriber_id, secret).finalize().into_bytes(),
+            base64::URL_SAFE_NO_PAD
+        );
+        Self(format!("{}.{}", subscriber_id, signature))
+    }
+
+    /// Return the subscriber id if `s` was signed with `secret`.
+    pub fn verify(s: &str, secret: &str) -> Result<Uuid, String> {
+        let (subscriber_id, signature) = s
+            .split_once('.')
+            .ok_or_else(|| format!("{} is not a valid unsubscribe token.", s))?;
+        let subscriber_id = Uuid::parse_str(subscriber_id)
+            .map_err(|_| format!("{} is not a valid unsubscribe token.", s))?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| format!("{} is not a valid unsubscribe token.", s))?;
+        mac(subscriber_id, secret)
+            .verify(&signature)
+            .map_err(|_| format!("{} has an invalid signature.", s))?;
+        Ok(subscriber_id)
+    }
+}
+
+fn mac(subscriber_id: Uuid, secret: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::assert_err;
+    use uuid::Uuid;
+
+    #[test]
+    fn a_signed_token_verifies_to_its_subscriber(){
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::sign(subscriber_id, "secret");
+        assert_eq!(UnsubscribeToken::verify(token.as_ref(), "secret"), Ok(subscriber_id));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected(){
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), "another secret");
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), "secret"));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_is_rejected(){
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), "secret");
+        let (_, signature) = token.as_ref().split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), signature);
+        assert_err!(UnsubscribeToken::verify(&forged, "secret"));
+    }
+
+    #[test]
+    fn garbage_is_rejected(){
+        assert_err!(UnsubscribeToken::verify("not-a-token", "secret"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::email_client::smtp::to_mime;
+use crate::email_client::{BoxError, EmailMessage, EmailTransport, SendEmailError};
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::PathBuf;
+
+/// Writes every message as an `.eml` file in a directory instead of sending it.
+///
+/// Handy for local development: open the files with any mail client.
+#[derive(Debug)]
+pub struct FileTransport {
+    directory: PathBuf,
+    writer: AsyncFileTransport<Tokio1Executor>
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        let directory = directory.into();
+        let writer = AsyncFileTransport::new(&directory);
+        Self { directory, writer }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<(), SendEmailError> {
+        let (envelope, email) = to_mime(message)?;
+        // A full or read-only disk may well be fixed before the next attempt
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| SendEmailError::Transient { source: e.into(), retry_after: None })?;
+        self.writer
+            .send_raw(&envelope, &email)
+            .await
+            .map_err(|e| SendEmailError::Transient { source: e.into(), retry_after: None })?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), BoxError> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, FileTransport};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn messages_are_written_as_eml_files(){
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = EmailClient::new(email(), FileTransport::new(&directory));
+
+        email_client
+            .send_email_with_headers(
+                email(),
+                "Weekly digest",
+                "<p>Hello</p>",
+                "Hello",
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")]
+            )
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let email = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(email.contains("Subject: Weekly digest"));
+        assert!(email.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+}
@@ -0,0 +1,308 @@
+mod file;
+mod postmark;
+mod sendgrid;
+mod smtp;
+
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use sendgrid::SendGridTransport;
+pub use smtp::{SmtpTls, SmtpTransport};
+
+use crate::domain::SubscriberEmail;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Boxed cause of a failed delivery, whatever the transport.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A fully addressed email, ready to be handed over to a transport.
+#[derive(Debug)]
+pub struct EmailMessage<'a> {
+    pub sender: &'a SubscriberEmail,
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    /// Extra headers, e.g. `List-Unsubscribe`.
+    pub headers: &'a [(&'a str, &'a str)]
+}
+
+/// Something able to deliver an `EmailMessage`: an HTTP API, an SMTP relay, a directory...
+///
+/// Implementations classify failures as transient or permanent so the delivery
+/// worker knows whether retrying is worth it.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync + std::fmt::Debug {
+    /// Short identifier used to label metrics, e.g. `sendgrid`.
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<(), SendEmailError>;
+
+    /// Check that messages could be handed over right now, for readiness probes.
+    async fn ping(&self) -> Result<(), BoxError>;
+}
+
+#[derive(Debug)]
+pub struct EmailClient{
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+    /// Labelled by transport and outcome, see `Metrics`.
+    emails_sent: Option<prometheus::IntCounterVec>
+}
+
+/// Why a delivery attempt failed, and whether trying again could help.
+#[derive(Debug)]
+pub enum SendEmailError {
+    /// Timeouts, connection errors, 5xx and 429 responses.
+    /// `retry_after` carries the provider's `Retry-After` hint, if any.
+    Transient {
+        source: BoxError,
+        retry_after: Option<Duration>
+    },
+    /// The provider rejected the message: retrying will not help.
+    Permanent(BoxError)
+}
+
+impl SendEmailError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SendEmailError::Transient { .. })
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SendEmailError::Transient { retry_after, .. } => *retry_after,
+            SendEmailError::Permanent(_) => None
+        }
+    }
+}
+
+impl std::fmt::Display for SendEmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendEmailError::Transient { source, .. } => write!(f, "Transient email delivery failure: {}", source),
+            SendEmailError::Permanent(source) => write!(f, "Permanent email delivery failure: {}", source)
+        }
+    }
+}
+
+impl std::error::Error for SendEmailError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SendEmailError::Transient { source, .. } => Some(source.as_ref()),
+            SendEmailError::Permanent(source) => Some(source.as_ref())
+        }
+    }
+}
+
+/// Longest `Retry-After` we honour: a provider asking for more is most
+/// likely misbehaving, and the delivery would never be retried.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many times a delivery is attempted and how long to wait in between.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration
+}
+
+impl RetryPolicy {
+    /// Whether another attempt is allowed after `attempts` have failed.
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// Delay before the next attempt, after `attempts` have failed.
+    ///
+    /// Doubles from `min_backoff` up to `max_backoff`; a `Retry-After` hint
+    /// from the provider wins if it asks us to wait longer, up to `MAX_RETRY_AFTER`.
+    pub fn backoff(&self, attempts: u32, retry_after: Option<Duration>) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(31);
+        let backoff = self
+            .min_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        match retry_after {
+            Some(retry_after) => backoff.max(retry_after.min(MAX_RETRY_AFTER)),
+            None => backoff
+        }
+    }
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+            emails_sent: None
+        }
+    }
+
+    /// Count every delivery attempt in `emails_sent`.
+    pub fn with_metrics(mut self, emails_sent: prometheus::IntCounterVec) -> Self {
+        self.emails_sent = Some(emails_sent);
+        self
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str
+    ) -> Result<(), SendEmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    /// Like `send_email`, adding custom headers (e.g. `List-Unsubscribe`) to the message.
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)]
+    ) -> Result<(), SendEmailError> {
+        let message = EmailMessage {
+            sender: &self.sender,
+            recipient: &recipient,
+            subject,
+            html_content,
+            text_content,
+            headers
+        };
+        let outcome = self.transport.send(&message).await;
+        if let Some(emails_sent) = &self.emails_sent {
+            let label = match &outcome {
+                Ok(()) => "sent",
+                Err(e) if e.is_transient() => "transient_error",
+                Err(_) => "permanent_error"
+            };
+            emails_sent.with_label_values(&[self.transport.name(), label]).inc();
+        }
+        outcome
+    }
+
+    /// See `EmailTransport::ping`.
+    pub async fn ping(&self) -> Result<(), BoxError> {
+        self.transport.ping().await
+    }
+}
+
+/// Send a request to an HTTP email API and classify the outcome.
+///
+/// Shared by the providers exposing a JSON API (SendGrid, Postmark).
+async fn send_http_request(request: RequestBuilder) -> Result<(), SendEmailError> {
+    let response = with_trace_context(request)
+        .send()
+        .await
+        .map_err(|source| {
+            // The request never got a response: the provider may well be fine next time
+            if source.is_timeout() || source.is_connect() || source.is_request() {
+                SendEmailError::Transient { source: source.into(), retry_after: None }
+            } else {
+                SendEmailError::Permanent(source.into())
+            }
+        })?;
+
+    let status = response.status();
+    let retry_after = retry_after(&response);
+    response.error_for_status().map_err(|source| {
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            SendEmailError::Transient { source: source.into(), retry_after }
+        } else {
+            SendEmailError::Permanent(source.into())
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Check that an HTTP email API answers at all.
+///
+/// Any response but a 5xx will do: we are not sending anything, so 401s
+/// and 404s only prove that the provider is up.
+async fn ping_http(request: RequestBuilder) -> Result<(), BoxError> {
+    let response = request.send().await?;
+    if response.status().is_server_error() {
+        return Err(format!("The email provider answered {}", response.status()).into());
+    }
+    Ok(())
+}
+
+/// Propagate the current trace to the provider as a W3C `traceparent` header.
+///
+/// Nothing is added unless spans are exported, see `telemetry::otlp_tracer`.
+fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers)
+    });
+    headers
+        .into_iter()
+        .fold(request, |request, (name, value)| request.header(name.as_str(), value))
+}
+
+/// Parse a `Retry-After` header, given either as delay-seconds or as an HTTP-date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_client::{RetryPolicy, MAX_RETRY_AFTER};
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum(){
+        let policy = retry_policy();
+        assert_eq!(policy.backoff(1, None), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2, None), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3, None), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10, None), Duration::from_secs(60));
+        assert_eq!(policy.backoff(u32::MAX, None), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn backoff_honours_a_longer_retry_after(){
+        let policy = retry_policy();
+        assert_eq!(policy.backoff(1, Some(Duration::from_secs(30))), Duration::from_secs(30));
+        assert_eq!(policy.backoff(3, Some(Duration::from_millis(10))), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn retry_after_is_capped(){
+        let policy = retry_policy();
+        assert_eq!(policy.backoff(1, Some(Duration::from_secs(u64::MAX))), MAX_RETRY_AFTER);
+    }
+
+    #[test]
+    fn no_retries_after_the_last_attempt(){
+        let policy = retry_policy();
+        assert!(policy.should_retry(4));
+        assert!(!policy.should_retry(5));
+    }
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60)
+        }
+    }
+}
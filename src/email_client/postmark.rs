@@ -0,0 +1,215 @@
+use crate::email_client::{
+    ping_http, send_http_request, BoxError, EmailMessage, EmailTransport, SendEmailError
+};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Delivers through Postmark's `/email` API.
+#[derive(Debug)]
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    server_token: Secret<String>
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    message_stream: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<EmailHeader<'a>>
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct EmailHeader<'a> {
+    name: &'a str,
+    value: &'a str
+}
+
+impl PostmarkTransport {
+    pub fn new(base_url: String, server_token: Secret<String>, timeout: Duration) -> Self {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            base_url,
+            server_token
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    fn name(&self) -> &'static str {
+        "postmark"
+    }
+
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<(), SendEmailError> {
+        let url = format!("{}/email", self.base_url);
+
+        let request_body = SendEmailRequest {
+            from: message.sender.as_ref(),
+            to: message.recipient.as_ref(),
+            subject: message.subject,
+            html_body: message.html_content,
+            text_body: message.text_content,
+            message_stream: "outbound",
+            headers: message.headers
+                .iter()
+                .map(|(name, value)| EmailHeader { name, value })
+                .collect()
+        };
+
+        let request = self.http_client
+            .post(&url)
+            .header("Accept", "application/json")
+            .header("X-Postmark-Server-Token", self.server_token.expose_secret())
+            .json(&request_body);
+        send_http_request(request).await
+    }
+
+    async fn ping(&self) -> Result<(), BoxError> {
+        ping_http(self.http_client.get(&self.base_url)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, PostmarkTransport};
+    use std::time::Duration;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use wiremock::matchers::{any, body_string_contains, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+    use claim::assert_ok;
+    use secrecy::Secret;
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> =
+                serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+                    && *body.get("MessageStream").unwrap() == serde_json::json!("outbound")
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_adds_them_to_the_message(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(body_string_contains(
+            r#""Headers":[{"Name":"List-Unsubscribe-Post","Value":"List-Unsubscribe=One-Click"}]"#
+        ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_with_headers(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")]
+            )
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn rejected_messages_are_permanent(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert!(!outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_transient(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    fn subject() -> String{
+        Sentence(1..2).fake()
+    }
+    fn content() -> String{
+        Paragraph(1..10).fake()
+    }
+    fn email() ->SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url:String) ->EmailClient {
+        EmailClient::new(
+            email(),
+            PostmarkTransport::new(base_url, Secret::new(Faker.fake()), Duration::from_millis(200))
+        )
+    }
+}
@@ -0,0 +1,345 @@
+use crate::email_client::{
+    ping_http, send_http_request, BoxError, EmailMessage, EmailTransport, SendEmailError
+};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Delivers through SendGrid's v3 `/mail/send` API.
+#[derive(Debug)]
+pub struct SendGridTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>
+}
+
+#[derive(Serialize, Debug)]
+struct SendEmailRequest{
+    from: EmailAddress,
+    personalizations: Vec<EmailRecipient>,
+    subject: String,
+    content: Vec<EmailContent>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>
+}
+#[derive(Serialize, Debug)]
+struct EmailRecipient{
+    to: Vec<EmailAddress>
+}
+#[derive(Serialize, Debug)]
+struct EmailAddress {
+    email: String
+}
+#[derive(Serialize, Debug)]
+struct EmailContent{
+    #[serde(rename="type")]
+    content_type: String,
+    value: String
+}
+
+impl SendGridTransport {
+    pub fn new(base_url: String, authorization_token: Secret<String>, timeout: Duration) -> Self {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SendGridTransport {
+    fn name(&self) -> &'static str {
+        "sendgrid"
+    }
+
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<(), SendEmailError> {
+        let url = format!("{}/mail/send", self.base_url);
+
+        let request_body = SendEmailRequest{
+            from: EmailAddress{ email: message.sender.as_ref().to_owned()},
+            personalizations: vec![
+                EmailRecipient{
+                    to: vec![EmailAddress{ email: message.recipient.as_ref().to_owned()}]
+                },
+            ],
+            subject: message.subject.to_owned(),
+            content: vec![
+                EmailContent { content_type:"text/plain".to_string(), value: message.text_content.to_owned()},
+                EmailContent { content_type:"text/html".to_string(), value: message.html_content.to_owned()}
+            ],
+            headers: message.headers
+                .iter()
+                .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+                .collect()
+        };
+
+        let request = self.http_client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.authorization_token.expose_secret()))
+            .json(&request_body);
+        send_http_request(request).await
+    }
+
+    async fn ping(&self) -> Result<(), BoxError> {
+        ping_http(self.http_client.get(&self.base_url)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, SendGridTransport};
+    use std::time::Duration;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use wiremock::matchers::{header_exists, header, path, method, any, body_string_contains};
+    use wiremock::{Mock, MockServer, ResponseTemplate, Request};
+    use claim::{assert_ok,assert_err};
+    use secrecy::Secret;
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    
+    struct SendEmailBodyMatcher;
+    
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> =
+                serde_json::from_slice(&request.body);
+
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("personalizations").unwrap().get(0).unwrap().get("to").unwrap().get(0).unwrap().get("email").is_some()
+                && body.get("from").unwrap().get("email").is_some()
+                    && body.get("subject").is_some()
+                    && *body.get("content").unwrap().get(0).unwrap().get("type").unwrap() == serde_json::json!("text/plain")
+                    && body.get("content").unwrap().get(0).unwrap().get("value").is_some()
+                    && *body.get("content").unwrap().get(1).unwrap().get("type").unwrap() == serde_json::json!("text/html")
+                    && body.get("content").unwrap().get(1).unwrap().get("value").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+
+    #[tokio::test]
+    async fn send_email_fires_a_request_to_base_url(){
+        let mock_server = MockServer::start().await; // full blown http server
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("Authorization"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/mail/send"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1) // should only receive only one call in this test
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200(){
+        let mock_server = MockServer::start().await; // full blown http server
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1) // should only receive only one call in this test
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500(){
+        let mock_server = MockServer::start().await; // full blown http server
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1) // should only receive only one call in this test
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_server_takes_too_long(){
+        let mock_server = MockServer::start().await; // full blown http server
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200)
+            .set_delay(Duration::from_secs(180));
+
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1) // should only receive only one call in this test
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_adds_them_to_the_message(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(body_string_contains(
+            r#""headers":{"List-Unsubscribe-Post":"List-Unsubscribe=One-Click"}"#
+        ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_with_headers(
+                email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")]
+            )
+            .await;
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_transient(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_transient(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200)
+            .set_delay(Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_permanent(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        assert!(!outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_is_transient_and_honours_retry_after(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err();
+        assert!(error.is_transient());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(120)));
+    }
+
+    #[tokio::test]
+    async fn the_current_trace_is_propagated_to_the_provider(){
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+        let tracer = opentelemetry::trace::TracerProvider::tracer(&provider, "test", None);
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        Mock::given(header_exists("traceparent"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .instrument(tracing::info_span!("Send a newsletter issue"))
+            .await;
+        assert_ok!(outcome);
+    }
+
+    fn subject() -> String{
+        Sentence(1..2).fake()
+    }
+    fn content() -> String{
+        Paragraph(1..10).fake()
+    }
+    fn email() ->SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url:String) ->EmailClient {
+        EmailClient::new(
+            email(),
+            SendGridTransport::new(base_url, Secret::new(Faker.fake()), Duration::from_millis(200))
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use crate::email_client::{BoxError, EmailMessage, EmailTransport, SendEmailError};
+use lettre::address::Envelope;
+use lettre::message::header::HeaderName;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// How the connection to the SMTP relay is secured.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Implicit TLS from the first byte (usually port 465).
+    Wrapper,
+    /// Plain connection upgraded with `STARTTLS` (usually port 587).
+    StartTls,
+    /// No encryption at all: only for local catch-all servers (MailHog, MailCatcher...).
+    None
+}
+
+/// Delivers through an SMTP relay.
+#[derive(Debug)]
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        timeout: Duration
+    ) -> Result<Self, String> {
+        let builder = match tls {
+            SmtpTls::Wrapper => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host))
+        }
+        .map_err(|e| format!("Invalid SMTP relay {}: {}", host, e))?;
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password.expose_secret().clone()));
+        }
+        Ok(Self { mailer: builder.build() })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, message: &EmailMessage<'_>) -> Result<(), SendEmailError> {
+        let (envelope, email) = to_mime(message)?;
+        self.mailer
+            .send_raw(&envelope, &email)
+            .await
+            .map_err(|e| {
+                // 5xx replies and messages the client refused to encode will fail again
+                if e.is_permanent() || e.is_client() {
+                    SendEmailError::Permanent(e.into())
+                } else {
+                    SendEmailError::Transient { source: e.into(), retry_after: None }
+                }
+            })?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), BoxError> {
+        if self.mailer.test_connection().await? {
+            Ok(())
+        } else {
+            Err("The SMTP relay did not accept our connection".into())
+        }
+    }
+}
+
+/// Render a message as a multipart/alternative RFC 5322 email.
+///
+/// Custom headers are prepended verbatim: header order does not matter, and
+/// lettre only supports statically known header names.
+pub(super) fn to_mime(message: &EmailMessage<'_>) -> Result<(Envelope, Vec<u8>), SendEmailError> {
+    let sender: Mailbox = message.sender.as_ref().parse()
+        .map_err(|e| SendEmailError::Permanent(Box::new(e)))?;
+    let recipient: Mailbox = message.recipient.as_ref().parse()
+        .map_err(|e| SendEmailError::Permanent(Box::new(e)))?;
+    let email = Message::builder()
+        .from(sender)
+        .to(recipient)
+        .subject(message.subject)
+        .multipart(MultiPart::alternative_plain_html(
+            message.text_content.to_owned(),
+            message.html_content.to_owned()
+        ))
+        .map_err(|e| SendEmailError::Permanent(Box::new(e)))?;
+
+    let mut raw = Vec::new();
+    for (name, value) in message.headers {
+        HeaderName::new_from_ascii((*name).to_owned())
+            .map_err(|e| SendEmailError::Permanent(Box::new(e)))?;
+        if value.contains(['\r', '\n']) {
+            return Err(SendEmailError::Permanent(
+                format!("Invalid value for the {} header", name).into()
+            ));
+        }
+        raw.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    raw.extend_from_slice(&email.formatted());
+    Ok((email.envelope().clone(), raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::smtp::to_mime;
+    use crate::email_client::EmailMessage;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+
+    #[test]
+    fn mime_messages_carry_both_parts_and_custom_headers(){
+        let sender = email();
+        let recipient = email();
+        let message = EmailMessage {
+            sender: &sender,
+            recipient: &recipient,
+            subject: "Weekly digest",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            headers: &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")]
+        };
+
+        let (envelope, email) = to_mime(&message).unwrap();
+        let email = String::from_utf8(email).unwrap();
+
+        assert_eq!(envelope.to()[0].to_string(), recipient.as_ref());
+        assert!(email.starts_with("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+        assert!(email.contains("Subject: Weekly digest"));
+        assert!(email.contains("multipart/alternative"));
+        assert!(email.contains("<p>Hello</p>"));
+    }
+
+    #[test]
+    fn header_injection_is_rejected(){
+        let sender = email();
+        let recipient = email();
+        let message = EmailMessage {
+            sender: &sender,
+            recipient: &recipient,
+            subject: "Weekly digest",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            headers: &[("List-Unsubscribe", "<https://example.com>\r\nBcc: victim@example.com")]
+        };
+
+        assert!(!to_mime(&message).unwrap_err().is_transient());
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+}
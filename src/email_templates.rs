@@ -0,0 +1,127 @@
+use serde::Serialize;
+use tera::{Context, Tera};
+
+/// A message body, ready to be handed over to the `EmailClient`.
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub html: String,
+    pub text: String
+}
+
+/// A typed context for one of the templates in the templates directory.
+///
+/// `NAME` is the template stem: `<NAME>.html` and `<NAME>.txt` must both exist.
+pub trait EmailTemplate: Serialize {
+    const NAME: &'static str;
+}
+
+/// Sent right after a subscription to check that the address belongs to the subscriber.
+#[derive(Serialize, Debug)]
+pub struct ConfirmationEmail<'a> {
+    pub subscriber_name: &'a str,
+    pub confirmation_link: &'a str,
+    pub unsubscribe_link: &'a str
+}
+
+impl EmailTemplate for ConfirmationEmail<'_> {
+    const NAME: &'static str = "confirmation";
+}
+
+/// Wraps a newsletter issue for one of its recipients.
+///
+/// The issue content is written by an admin and is inserted as-is.
+#[derive(Serialize, Debug)]
+pub struct NewsletterEmail<'a> {
+    pub subscriber_name: &'a str,
+    pub title: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_link: &'a str
+}
+
+impl EmailTemplate for NewsletterEmail<'_> {
+    const NAME: &'static str = "newsletter";
+}
+
+/// Email layouts, partials and bodies, loaded once at startup.
+///
+/// Values are HTML-escaped in `.html` templates, never in `.txt` ones.
+#[derive(Clone, Debug)]
+pub struct EmailTemplates {
+    tera: Tera
+}
+
+impl EmailTemplates {
+    /// Load every template found (recursively) in `directory`.
+    pub fn load(directory: &str) -> Result<Self, tera::Error> {
+        let mut tera = Tera::new(&format!("{}/**/*", directory.trim_end_matches('/')))?;
+        tera.autoescape_on(vec![".html"]);
+        Ok(Self { tera })
+    }
+
+    pub fn render<T: EmailTemplate>(&self, email: &T) -> Result<RenderedEmail, tera::Error> {
+        let context = Context::from_serialize(email)?;
+        Ok(RenderedEmail {
+            html: self.tera.render(&format!("{}.html", T::NAME), &context)?,
+            text: self.tera.render(&format!("{}.txt", T::NAME), &context)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_templates::{ConfirmationEmail, EmailTemplates, NewsletterEmail};
+
+    fn templates() -> EmailTemplates {
+        EmailTemplates::load("templates/emails").expect("Failed to load the email templates")
+    }
+
+    #[test]
+    fn confirmation_email_contains_both_links(){
+        let email = templates()
+            .render(&ConfirmationEmail {
+                subscriber_name: "Ursula",
+                confirmation_link: "https://example.com/confirm?subscription_token=abc",
+                unsubscribe_link: "https://example.com/unsubscribe?token=xyz"
+            })
+            .unwrap();
+
+        for body in &[&email.html, &email.text] {
+            assert!(body.contains("Ursula"));
+            assert!(body.contains("https://example.com/confirm?subscription_token=abc"));
+            assert!(body.contains("https://example.com/unsubscribe?token=xyz"));
+        }
+        assert!(email.text.contains("Visit"));
+    }
+
+    #[test]
+    fn subscriber_names_are_escaped_in_html_only(){
+        let email = templates()
+            .render(&ConfirmationEmail {
+                subscriber_name: "Tom & Jerry",
+                confirmation_link: "https://example.com/confirm",
+                unsubscribe_link: "https://example.com/unsubscribe"
+            })
+            .unwrap();
+
+        assert!(email.html.contains("Tom &amp; Jerry"));
+        assert!(email.text.contains("Tom & Jerry"));
+    }
+
+    #[test]
+    fn newsletter_content_is_inserted_as_is(){
+        let email = templates()
+            .render(&NewsletterEmail {
+                subscriber_name: "Ursula",
+                title: "Issue #1",
+                html_content: "<p>Newsletter body as HTML</p>",
+                text_content: "Newsletter body as plain text",
+                unsubscribe_link: "https://example.com/unsubscribe?token=xyz"
+            })
+            .unwrap();
+
+        assert!(email.html.contains("<p>Newsletter body as HTML</p>"));
+        assert!(email.text.contains("Newsletter body as plain text"));
+        assert!(email.html.contains("https://example.com/unsubscribe?token=xyz"));
+    }
+}
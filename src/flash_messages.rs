@@ -0,0 +1,38 @@
+//! One-shot messages carried across a redirect in a short-lived cookie.
+//!
+//! Messages are always HTML-escaped when rendered, so a tampered cookie
+//! cannot inject markup.
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::HttpRequest;
+
+const FLASH_COOKIE: &str = "_flash";
+
+/// Cookie carrying `message` to the next request.
+pub fn flash_cookie(message: &str) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE, base64::encode(message))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+/// Cookie that removes the flash message once it has been displayed.
+pub fn clear_flash_cookie() -> Cookie<'static> {
+    let mut cookie = flash_cookie("");
+    cookie.make_removal();
+    cookie
+}
+
+/// The message left by the previous response, if any.
+pub fn read_flash(request: &HttpRequest) -> Option<String> {
+    let cookie = request.cookie(FLASH_COOKIE)?;
+    let bytes = base64::decode(cookie.value()).ok()?;
+    String::from_utf8(bytes).ok().filter(|message| !message.is_empty())
+}
+
+/// The flash message rendered as HTML paragraphs, ready to be embedded in a page.
+pub fn render_flash(request: &HttpRequest) -> String {
+    read_flash(request)
+        .map(|message| format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)))
+        .unwrap_or_default()
+}
@@ -0,0 +1,245 @@
+use super::IdempotencyKey;
+use crate::routes::{problem_details, ValidationErrors};
+use actix_web::body::{to_bytes, AnyBody};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::convert::TryFrom;
+use std::future::Future;
+
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+    /// First time we see this key: run the handler, then `save_response`.
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(HttpResponse),
+    /// The key was first used for another request.
+    RejectReusedKey
+}
+
+/// Run `handler` at most once per `Idempotency-Key` and request path.
+///
+/// `handler` does its writes on the transaction it is given, and hands it
+/// back with its response. With a key, that transaction holds the key's row:
+/// the writes and the saved response are committed together, on a single
+/// connection. Server errors are neither committed nor saved, so the client
+/// can retry them.
+///
+/// Repeats of `request_body` get the first response replayed, other bodies
+/// sent with the same key a 422; concurrent repeats wait on the row lock
+/// taken by the first one.
+pub async fn run_idempotently<F, Fut>(
+    pool: &PgPool,
+    request: &HttpRequest,
+    request_body: &impl Serialize,
+    handler: F
+) -> HttpResponse
+where
+    F: FnOnce(Transaction<'static, Postgres>) -> Fut,
+    Fut: Future<Output = (Transaction<'static, Postgres>, HttpResponse)>
+{
+    let idempotency_key = match request.headers().get("Idempotency-Key") {
+        None => return run_in_transaction(pool, handler).await,
+        Some(value) => match value
+            .to_str()
+            .map_err(|e| e.to_string())
+            .and_then(|s| IdempotencyKey::try_from(s.to_owned()))
+        {
+            Ok(key) => key,
+            Err(e) => {
+                let mut errors = ValidationErrors::default();
+                errors.add("Idempotency-Key", e);
+                return errors.problem_details();
+            }
+        }
+    };
+    let request_hash = hash_request(request_body);
+    let transaction = match try_processing(pool, &idempotency_key, request.path(), &request_hash).await {
+        Ok(NextAction::StartProcessing(transaction)) => transaction,
+        Ok(NextAction::ReturnSavedResponse(saved_response)) => return saved_response,
+        Ok(NextAction::RejectReusedKey) => {
+            return problem_details(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency key reused",
+                "This Idempotency-Key was already used for a different request.".into(),
+                &[]
+            );
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let (transaction, response) = handler(transaction).await;
+    if response.status().is_server_error() {
+        // Dropping the transaction rolls the writes back and releases the key
+        return response;
+    }
+    match save_response(transaction, &idempotency_key, request.path(), response).await {
+        Ok(response) => response,
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Same as `run_idempotently`, for requests without a key.
+async fn run_in_transaction<F, Fut>(pool: &PgPool, handler: F) -> HttpResponse
+where
+    F: FnOnce(Transaction<'static, Postgres>) -> Fut,
+    Fut: Future<Output = (Transaction<'static, Postgres>, HttpResponse)>
+{
+    let transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let (transaction, response) = handler(transaction).await;
+    if response.status().is_server_error() {
+        return response;
+    }
+    match transaction.commit().await {
+        Ok(()) => response,
+        Err(_) => HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Tells requests sent with the same key apart, whatever the order of their fields.
+fn hash_request(request_body: &impl Serialize) -> String {
+    let body = serde_json::to_vec(request_body).unwrap_or_default();
+    Sha256::digest(&body).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[tracing::instrument(
+    name = "Claim an idempotency key",
+    skip(pool, idempotency_key, request_hash)
+)]
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    request_path: &str,
+    request_hash: &str
+) -> Result<NextAction, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    // Blocks until a concurrent request holding the same key commits or rolls back
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (idempotency_key, request_path, request_hash, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+        idempotency_key.as_ref(),
+        request_path,
+        request_hash,
+        Utc::now()
+    )
+        .execute(&mut transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?
+        .rows_affected();
+    if n_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+    let saved = get_saved_response(&mut transaction, idempotency_key, request_path)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+    match saved.request_hash {
+        Some(saved_hash) if saved_hash != request_hash => Ok(NextAction::RejectReusedKey),
+        _ => Ok(NextAction::ReturnSavedResponse(saved.response))
+    }
+}
+
+struct SavedResponse {
+    request_hash: Option<String>,
+    response: HttpResponse
+}
+
+async fn get_saved_response(
+    transaction: &mut Transaction<'_, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    request_path: &str
+) -> Result<Option<SavedResponse>, sqlx::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            request_hash,
+            response_status_code as "response_status_code!",
+            response_header_names as "response_header_names!",
+            response_header_values as "response_header_values!",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+            idempotency_key = $1 AND
+            request_path = $2
+        "#,
+        idempotency_key.as_ref(),
+        request_path
+    )
+        .fetch_optional(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    let r = match saved_response {
+        Some(r) => r,
+        None => return Ok(None)
+    };
+    let status_code = StatusCode::from_u16(r.response_status_code as u16)
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    let mut response = HttpResponse::build(status_code);
+    for (name, value) in r.response_header_names.into_iter().zip(r.response_header_values) {
+        response.append_header((name, value));
+    }
+    Ok(Some(SavedResponse { request_hash: r.request_hash, response: response.body(r.response_body) }))
+}
+
+#[tracing::instrument(
+    name = "Save the response for an idempotency key",
+    skip(transaction, idempotency_key, http_response)
+)]
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    request_path: &str,
+    http_response: HttpResponse
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response_head, body) = http_response.into_parts();
+    let body = to_bytes(body).await?;
+    let status_code = response_head.status().as_u16() as i16;
+    let (header_names, header_values): (Vec<String>, Vec<Vec<u8>>) = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.as_str().to_owned(), value.as_bytes().to_owned()))
+        .unzip();
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_header_names = $4,
+            response_header_values = $5,
+            response_body = $6
+        WHERE
+            idempotency_key = $1 AND
+            request_path = $2
+        "#,
+        idempotency_key.as_ref(),
+        request_path,
+        status_code,
+        &header_names,
+        &header_values,
+        body.as_ref()
+    )
+        .execute(&mut transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        })?;
+    transaction
+        .commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(response_head.set_body(AnyBody::from(body)))
+}
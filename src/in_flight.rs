@@ -0,0 +1,116 @@
+//! The requests the server is still handling, so that a graceful shutdown
+//! can wait for them.
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+pub struct InFlightRequests(Arc<AtomicUsize>);
+
+impl InFlightRequests {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once no request is in flight.
+    pub async fn drained(&self) {
+        while self.count() > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn start(&self) -> InFlightGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(Arc::clone(&self.0))
+    }
+}
+
+/// Counts a request until dropped: when it completes, but also when its
+/// future is dropped midway (client gone, handler panicked).
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Keep `InFlightRequests` up to date with every request handled by the application.
+pub struct TrackInFlight(pub InFlightRequests);
+
+impl<S, B> Transform<S, ServiceRequest> for TrackInFlight
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = TrackInFlightMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TrackInFlightMiddleware {
+            service: Rc::new(service),
+            in_flight: self.0.clone()
+        }))
+    }
+}
+
+pub struct TrackInFlightMiddleware<S> {
+    service: Rc<S>,
+    in_flight: InFlightRequests
+}
+
+impl<S, B> Service<ServiceRequest> for TrackInFlightMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let guard = self.in_flight.start();
+        Box::pin(async move {
+            let outcome = service.call(req).await;
+            drop(guard);
+            outcome
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::in_flight::{InFlightRequests, TrackInFlight};
+    use actix_web::dev::Service;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::time::Duration;
+
+    #[actix_rt::test]
+    async fn requests_are_counted_until_they_complete_or_are_dropped(){
+        let in_flight = InFlightRequests::default();
+        let app = test::init_service(
+            App::new()
+                .wrap(TrackInFlight(in_flight.clone()))
+                .route("/pending", web::get().to(std::future::pending::<HttpResponse>))
+                .route("/ready", web::get().to(HttpResponse::Ok))
+        ).await;
+
+        let request = app.call(test::TestRequest::get().uri("/pending").to_request());
+        let outcome = tokio::time::timeout(Duration::from_millis(50), request).await;
+        assert!(outcome.is_err());
+        assert_eq!(in_flight.count(), 0);
+
+        test::call_service(&app, test::TestRequest::get().uri("/ready").to_request()).await;
+        assert_eq!(in_flight.count(), 0);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::domain::{SubscriberEmail, UnsubscribeToken};
+use crate::email_client::{EmailClient, RetryPolicy, SendEmailError, MAX_RETRY_AFTER};
+use crate::email_templates::{EmailTemplates, NewsletterEmail};
+use crate::pii::Pii;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::convert::TryFrom;
+use std::time::Duration;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue
+}
+
+/// Delivers queued newsletter issues, one email per task.
+pub struct IssueDeliveryWorker {
+    pub pool: PgPool,
+    pub email_client: EmailClient,
+    pub templates: EmailTemplates,
+    pub retry_policy: RetryPolicy,
+    /// Used to build the unsubscribe link attached to every issue.
+    pub base_url: String,
+    pub hmac_secret: Secret<String>
+}
+
+impl IssueDeliveryWorker {
+    /// Drain `issue_delivery_queue` until `shutdown` flips to `true` (or is dropped).
+    ///
+    /// Every replica can run one of these: rows are claimed with
+    /// `FOR UPDATE SKIP LOCKED`, so two workers never deliver the same email.
+    /// A delivery in progress is always finished before stopping.
+    pub async fn run_until_stopped(self, mut shutdown: watch::Receiver<bool>) -> Result<(), std::io::Error> {
+        while !*shutdown.borrow() {
+            let delay = match self.try_execute_task().await {
+                Ok(ExecutionOutcome::EmptyQueue) => Duration::from_secs(10),
+                Err(_) => Duration::from_secs(1),
+                Ok(ExecutionOutcome::TaskCompleted) => continue
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                changed = shutdown.changed() => if changed.is_err() { break }
+            }
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Deliver a queued newsletter issue",
+        skip(self),
+        fields(
+            newsletter_issue_id = tracing::field::Empty,
+            subscriber_email = tracing::field::Empty
+        ),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, sqlx::Error> {
+        let task = dequeue_task(&self.pool).await?;
+        let (transaction, task) = match task {
+            Some(task) => task,
+            None => return Ok(ExecutionOutcome::EmptyQueue)
+        };
+        tracing::Span::current()
+            .record("newsletter_issue_id", &tracing::field::display(task.newsletter_issue_id))
+            .record("subscriber_email", &tracing::field::display(Pii(&task.subscriber_email)));
+        let subscriber_id = match (task.subscriber_id, task.subscriber_status.as_deref()) {
+            (Some(subscriber_id), Some("confirmed")) => subscriber_id,
+            _ => {
+                tracing::info!("Skipping a subscriber who is no longer confirmed");
+                delete_task(transaction, &task).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+        };
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => {
+                let issue = get_issue(&self.pool, task.newsletter_issue_id).await?;
+                let unsubscribe_link = format!(
+                    "{}/subscriptions/unsubscribe?token={}",
+                    self.base_url,
+                    UnsubscribeToken::sign(subscriber_id, self.hmac_secret.expose_secret()).as_ref()
+                );
+                let body = self.templates.render(&NewsletterEmail {
+                    subscriber_name: task.subscriber_name.as_deref().unwrap_or_default(),
+                    title: &issue.title,
+                    html_content: &issue.html_content,
+                    text_content: &issue.text_content,
+                    unsubscribe_link: &unsubscribe_link
+                });
+                let body = match body {
+                    Ok(body) => body,
+                    Err(e) => {
+                        // Templates are loaded at startup: this will not fix itself by retrying
+                        let error = SendEmailError::Permanent(e.into());
+                        return handle_failed_delivery(transaction, task, error, &self.retry_policy).await;
+                    }
+                };
+                let outcome = self.email_client
+                    .send_email_with_headers(
+                        email,
+                        &issue.title,
+                        &body.html,
+                        &body.text,
+                        &[
+                            ("List-Unsubscribe", &format!("<{}>", unsubscribe_link)),
+                            ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")
+                        ]
+                    )
+                    .await;
+                if let Err(e) = outcome {
+                    return handle_failed_delivery(transaction, task, e, &self.retry_policy).await;
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.message = %e,
+                    "Skipping a confirmed subscriber. \
+                    Their stored contact details are invalid"
+                );
+            }
+        }
+        delete_task(transaction, &task).await?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+}
+
+/// Reschedule a transient failure, or dead-letter the task once it is
+/// permanent or the retry budget is spent.
+async fn handle_failed_delivery(
+    transaction: PgTransaction,
+    task: DeliveryTask,
+    error: SendEmailError,
+    retry_policy: &RetryPolicy
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let attempts = u32::try_from(task.n_retries).unwrap_or(0) + 1;
+    if error.is_transient() && retry_policy.should_retry(attempts) {
+        let backoff = retry_policy.backoff(attempts, error.retry_after());
+        tracing::warn!(
+            error.message = %error,
+            attempts,
+            backoff_milliseconds = backoff.as_millis() as u64,
+            "Failed to deliver issue to a confirmed subscriber. Retrying later."
+        );
+        reschedule_task(transaction, &task, backoff).await?;
+    } else {
+        tracing::error!(
+            error.message = %error,
+            attempts,
+            "Failed to deliver issue to a confirmed subscriber. Moving it to the dead letters."
+        );
+        dead_letter_task(transaction, &task, attempts, &error.to_string()).await?;
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i32,
+    /// `None` if the subscriber has been deleted since the issue was published.
+    subscriber_id: Option<Uuid>,
+    subscriber_status: Option<String>,
+    subscriber_name: Option<String>
+}
+
+#[tracing::instrument(
+    name = "Dequeue a newsletter delivery task",
+    skip(pool)
+)]
+async fn dequeue_task(
+    pool: &PgPool
+) -> Result<Option<(PgTransaction, DeliveryTask)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            s.id AS "subscriber_id?",
+            s.status AS "subscriber_status?",
+            s.name AS "subscriber_name?"
+        FROM issue_delivery_queue q
+        LEFT JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+        .fetch_optional(&mut transaction)
+        .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(
+    name = "Delete a completed newsletter delivery task",
+    skip(transaction, task)
+)]
+async fn delete_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask
+) -> Result<(), sqlx::Error> {
+    remove_from_queue(&mut transaction, task).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn remove_from_queue(
+    transaction: &mut PgTransaction,
+    task: &DeliveryTask
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Reschedule a failed newsletter delivery task",
+    skip(transaction, task)
+)]
+async fn reschedule_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    backoff: Duration
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    // The retry policy bounds `backoff`: the fallback only guards against overflows
+    let execute_after = chrono::Duration::from_std(backoff)
+        .ok()
+        .and_then(|backoff| now.checked_add_signed(backoff))
+        .unwrap_or_else(|| now + chrono::Duration::from_std(MAX_RETRY_AFTER).unwrap());
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
+    )
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Move a newsletter delivery task to the dead letters",
+    skip(transaction, task, last_error)
+)]
+async fn dead_letter_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: u32,
+    last_error: &str
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        i32::try_from(n_attempts).unwrap_or(i32::MAX),
+        last_error,
+        Utc::now()
+    )
+        .execute(&mut transaction)
+        .await?;
+    remove_from_queue(&mut transaction, task).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// A delivery that exhausted its retries or failed permanently.
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_email: String,
+    pub n_attempts: i32,
+    pub last_error: String,
+    pub failed_at: chrono::DateTime<Utc>
+}
+
+#[tracing::instrument(
+    name = "List dead-lettered newsletter deliveries",
+    skip(pool)
+)]
+pub async fn list_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at
+        FROM issue_delivery_dead_letters
+        ORDER BY failed_at
+        "#,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+/// Put dead-lettered deliveries back in the queue with a fresh retry budget.
+///
+/// Requeues everything when `newsletter_issue_id` is `None`.
+/// Returns the number of deliveries that were requeued.
+#[tracing::instrument(
+    name = "Requeue dead-lettered newsletter deliveries",
+    skip(pool)
+)]
+pub async fn requeue_dead_letters(
+    pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_dead_letters
+            WHERE $1::uuid IS NULL OR newsletter_issue_id = $1
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, subscriber_email FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id
+    )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    transaction.commit().await?;
+    Ok(requeued)
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String
+}
+
+#[tracing::instrument(
+    name = "Get a newsletter issue",
+    skip(pool)
+)]
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+        .fetch_one(pool)
+        .await?;
+    Ok(issue)
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// What a single cleanup pass removed.
+#[derive(Debug, Default, PartialEq)]
+pub struct PurgeReport {
+    pub expired_tokens: u64,
+    pub unconfirmed_subscribers: u64,
+    pub full_rate_limit_buckets: u64,
+    pub expired_idempotency_keys: u64,
+    pub expired_sessions: u64
+}
+
+/// Periodically deletes expired confirmation tokens, subscribers who never
+/// confirmed, rate limit buckets that are full again, old idempotency keys
+/// and expired sessions.
+///
+/// The expired tokens of a pending subscriber are kept as long as the
+/// subscriber is: following the link shows "expired", rather than "invalid".
+pub struct Janitor {
+    pub pool: PgPool,
+    /// Pending subscribers older than this are deleted.
+    pub unconfirmed_subscriber_retention: Duration,
+    /// Responses saved for an `Idempotency-Key` older than this are deleted:
+    /// a retry with the same key is then handled as a new request.
+    pub idempotency_key_retention: Duration,
+    /// Time between two cleanup passes.
+    pub interval: Duration
+}
+
+impl Janitor {
+    /// Purge every `interval` until `shutdown` flips to `true` (or is dropped).
+    pub async fn run_until_stopped(self, mut shutdown: watch::Receiver<bool>) -> Result<(), std::io::Error> {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                changed = shutdown.changed() => if changed.is_err() { return Ok(()) }
+            }
+            if *shutdown.borrow() {
+                return Ok(());
+            }
+            // Failures are logged by `purge`: try again at the next pass
+            let _ = self.purge().await;
+        }
+    }
+
+    /// Run a single cleanup pass.
+    ///
+    /// Replicas can run it concurrently: every delete is idempotent.
+    #[tracing::instrument(
+        name = "Purge expired subscription tokens, unconfirmed subscribers, full rate limit buckets, old idempotency keys and expired sessions",
+        skip(self),
+        err
+    )]
+    pub async fn purge(&self) -> Result<PurgeReport, sqlx::Error> {
+        let pending_cutoff = cutoff(self.unconfirmed_subscriber_retention);
+        let mut transaction = self.pool.begin().await?;
+        let expired_tokens = sqlx::query!(
+            r#"
+            DELETE FROM subscription_tokens
+            WHERE subscriber_id IN (
+                SELECT id FROM subscriptions
+                WHERE
+                    (status = 'pending_confirmation' AND subscribed_at < $1) OR
+                    (status <> 'pending_confirmation' AND subscription_tokens.expires_at < now())
+            )
+            "#,
+            pending_cutoff
+        )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        let unconfirmed_subscribers = sqlx::query!(
+            r#"
+            DELETE FROM subscriptions
+            WHERE status = 'pending_confirmation' AND subscribed_at < $1
+            "#,
+            pending_cutoff
+        )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        transaction.commit().await?;
+        // A full bucket behaves exactly like a missing one
+        let full_rate_limit_buckets = sqlx::query!(
+            r#"DELETE FROM rate_limit_buckets WHERE full_at <= now()"#
+        )
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        let idempotency_cutoff = cutoff(self.idempotency_key_retention);
+        let expired_idempotency_keys = sqlx::query!(
+            r#"DELETE FROM idempotency WHERE created_at < $1"#,
+            idempotency_cutoff
+        )
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        // `PostgresSessionStore` ignores them already, but never deletes them
+        let expired_sessions = sqlx::query!(
+            r#"DELETE FROM sessions WHERE expires_at < now()"#
+        )
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        let report = PurgeReport {
+            expired_tokens,
+            unconfirmed_subscribers,
+            full_rate_limit_buckets,
+            expired_idempotency_keys,
+            expired_sessions
+        };
+        tracing::info!(
+            expired_tokens = report.expired_tokens,
+            unconfirmed_subscribers = report.unconfirmed_subscribers,
+            full_rate_limit_buckets = report.full_rate_limit_buckets,
+            expired_idempotency_keys = report.expired_idempotency_keys,
+            expired_sessions = report.expired_sessions,
+            "Purged stale subscription data"
+        );
+        Ok(report)
+    }
+}
+
+/// `retention` ago, or `None` if that is further back than can be told: a
+/// `NULL` cutoff matches no row, so nothing is purged.
+fn cutoff(retention: Duration) -> Option<DateTime<Utc>> {
+    chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+}
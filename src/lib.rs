@@ -1,7 +1,24 @@
+// `HttpResponse` implements `Future` in the actix-web 4 betas, which makes clippy
+// flag every handler returning it as yielding an un-awaited future.
+#![allow(clippy::async_yields_async)]
 
+pub mod admin_cli;
+pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod csrf;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod flash_messages;
+pub mod idempotency;
+pub mod in_flight;
+pub mod issue_delivery_worker;
+pub mod janitor;
+pub mod metrics;
+pub mod pii;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_state;
 pub mod startup;
 pub mod telemetry;
@@ -1,17 +1,28 @@
 
 use zero2prod::configuration::get_configuration;
+use zero2prod::pii::set_pii_mode;
 use zero2prod::startup::{Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 
 #[actix_web::main]
 async fn main() ->std::io::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+    let configuration = get_configuration().expect("Failed to read configuration file");
+
+    let otlp_tracer = configuration.telemetry.tracer().expect("Failed to build the OTLP exporter");
+    let subscriber = get_subscriber(
+        configuration.telemetry.service_name.clone(),
+        "info".into(),
+        std::io::stdout,
+        otlp_tracer
+    );
     init_subscriber(subscriber);
+    set_pii_mode(configuration.telemetry.pii, configuration.telemetry.pii_hash_key.clone());
 
-    let configuration = get_configuration().expect("Failed to read configuration file");
     let application = Application::build(configuration).await?;
     application.run_until_stopped().await?;
+    // Ship the spans still sitting in the exporter's batch
+    opentelemetry::global::shutdown_tracer_provider();
 
     Ok(())
 }
@@ -0,0 +1,161 @@
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder
+};
+use crate::in_flight::InFlightRequests;
+use sqlx::PgPool;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Everything exposed on `/metrics`.
+///
+/// Each `Application` owns its own registry, so several instances can live in
+/// the same process (e.g. in tests) without clashing.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    http_requests_in_flight: IntGauge,
+    /// Handed over to the `EmailClient`s, see `EmailClient::with_metrics`.
+    pub emails_sent: IntCounterVec,
+    db_pool_connections: IntGaugeVec,
+    subscribers: IntGaugeVec
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled, by route and status."),
+            &["method", "route", "status"]
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency, by route and status."),
+            &["method", "route", "status"]
+        )?;
+        let http_requests_in_flight = IntGauge::new(
+            "http_requests_in_flight", "HTTP requests currently being handled."
+        )?;
+        let emails_sent = IntCounterVec::new(
+            Opts::new("emails_sent_total", "Email delivery attempts, by transport and outcome."),
+            &["transport", "outcome"]
+        )?;
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Postgres connections held by the pool, by state."),
+            &["state"]
+        )?;
+        let subscribers = IntGaugeVec::new(
+            Opts::new("subscribers", "Subscribers, by status."),
+            &["status"]
+        )?;
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(http_requests_in_flight.clone()))?;
+        registry.register(Box::new(emails_sent.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(subscribers.clone()))?;
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            http_requests_in_flight,
+            emails_sent,
+            db_pool_connections,
+            subscribers
+        })
+    }
+
+    /// Refresh the gauges read from the database and `in_flight`, and encode
+    /// every metric in the Prometheus text format.
+    pub async fn render(&self, pool: &PgPool, in_flight: &InFlightRequests) -> Result<String, anyhow::Error> {
+        self.http_requests_in_flight.set(in_flight.count() as i64);
+        let size = i64::from(pool.size());
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections.with_label_values(&["in_use"]).set(size - idle);
+
+        let counts = sqlx::query!(
+            r#"SELECT status, COUNT(*) AS "count!" FROM subscriptions GROUP BY status"#
+        )
+            .fetch_all(pool)
+            .await?;
+        // Statuses nobody is in anymore must drop to zero, not keep their last value
+        self.subscribers.reset();
+        for row in counts {
+            self.subscribers.with_label_values(&[&row.status]).set(row.count);
+        }
+
+        Ok(TextEncoder::new().encode_to_string(&self.registry.gather())?)
+    }
+
+    fn observe_request(&self, method: &str, route: &str, status: u16, started_at: Instant) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(started_at.elapsed().as_secs_f64());
+    }
+}
+
+/// Count and time every request handled by the application.
+///
+/// Requests are labelled by route pattern (e.g. `/subscriptions/confirm`),
+/// never by raw path, to keep the number of series bounded.
+pub struct RecordMetrics(pub Metrics);
+
+impl<S, B> Transform<S, ServiceRequest> for RecordMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RecordMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RecordMetricsMiddleware {
+            service: Rc::new(service),
+            metrics: self.0.clone()
+        }))
+    }
+}
+
+pub struct RecordMetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Metrics
+}
+
+impl<S, B> Service<ServiceRequest> for RecordMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let metrics = self.metrics.clone();
+        let started_at = Instant::now();
+        let method = req.method().to_string();
+        let route = req.match_pattern().unwrap_or_else(|| "unmatched".into());
+        Box::pin(async move {
+            let outcome = service.call(req).await;
+            let status = match &outcome {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().status_code()
+            };
+            metrics.observe_request(&method, &route, status.as_u16(), started_at);
+            outcome
+        })
+    }
+}
@@ -0,0 +1,126 @@
+use hmac::{Hmac, Mac, NewMac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+/// How personal data (e.g. subscriber emails) shows up in logs and spans.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PiiMode {
+    /// `[REDACTED]`, nothing of the value leaks.
+    Redacted,
+    /// `u***@example.com`: enough to eyeball an issue, not to contact anyone.
+    Masked,
+    /// A truncated HMAC-SHA256, keyed with `telemetry.pii_hash_key`: lets us
+    /// correlate entries about the same subscriber, but not guess who it is by
+    /// hashing candidate emails.
+    Hashed
+}
+
+static MODE: AtomicU8 = AtomicU8::new(PiiMode::Redacted as u8);
+static HASH_KEY: OnceLock<Secret<String>> = OnceLock::new();
+
+/// Choose how every `Pii` value is rendered from now on.
+///
+/// It should be called once, at startup: values are redacted until then.
+/// Hashed values stay redacted without a `hash_key`.
+pub fn set_pii_mode(mode: PiiMode, hash_key: Option<Secret<String>>) {
+    if let Some(hash_key) = hash_key {
+        // Only the first key counts: hashes must not change under our feet
+        let _ = HASH_KEY.set(hash_key);
+    }
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+fn pii_mode() -> PiiMode {
+    match MODE.load(Ordering::Relaxed) {
+        m if m == PiiMode::Masked as u8 => PiiMode::Masked,
+        m if m == PiiMode::Hashed as u8 => PiiMode::Hashed,
+        _ => PiiMode::Redacted
+    }
+}
+
+/// Wrap personal data before recording it in a span or a log line.
+///
+/// Both `Display` and `Debug` follow the mode chosen with `set_pii_mode`.
+pub struct Pii<T>(pub T);
+
+impl<T: AsRef<str>> Pii<T> {
+    pub fn render(&self, mode: PiiMode) -> String {
+        let value = self.0.as_ref();
+        match mode {
+            PiiMode::Redacted => "[REDACTED]".into(),
+            PiiMode::Masked => match value.rsplit_once('@') {
+                Some((local_part, domain)) => format!("{}@{}", mask(local_part), domain),
+                None => mask(value)
+            },
+            PiiMode::Hashed => match HASH_KEY.get() {
+                Some(key) => hash(value, key.expose_secret()),
+                None => "[REDACTED]".into()
+            }
+        }
+    }
+}
+
+fn hash(value: &str, key: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(value.as_bytes());
+    let hex: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("hmac:{}", &hex[..16])
+}
+
+/// Keep the first character only.
+fn mask(value: &str) -> String {
+    match value.chars().next() {
+        Some(first) => format!("{}***", first),
+        None => String::new()
+    }
+}
+
+impl<T: AsRef<str>> std::fmt::Display for Pii<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(pii_mode()))
+    }
+}
+
+impl<T: AsRef<str>> std::fmt::Debug for Pii<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pii({})", self.render(pii_mode()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pii::{hash, Pii, PiiMode};
+
+    #[test]
+    fn values_are_redacted_by_default(){
+        assert_eq!(Pii("ursula@example.com").to_string(), "[REDACTED]");
+        assert_eq!(format!("{:?}", Pii("ursula@example.com")), "Pii([REDACTED])");
+    }
+
+    #[test]
+    fn masked_emails_keep_their_domain(){
+        assert_eq!(Pii("ursula@example.com").render(PiiMode::Masked), "u***@example.com");
+        assert_eq!(Pii("Ursula Le Guin").render(PiiMode::Masked), "U***");
+        assert_eq!(Pii("").render(PiiMode::Masked), "");
+    }
+
+    #[test]
+    fn hashed_values_are_stable_and_do_not_leak(){
+        let hashed = hash("ursula@example.com", "key");
+        assert_eq!(hashed, hash("ursula@example.com", "key"));
+        assert_ne!(hashed, hash("ursula@example.org", "key"));
+        assert!(hashed.starts_with("hmac:"));
+        assert!(!hashed.contains("ursula"));
+    }
+
+    #[test]
+    fn hashes_cannot_be_computed_without_the_key(){
+        assert_ne!(hash("ursula@example.com", "key"), hash("ursula@example.com", "another key"));
+        // No key was set in this process: nothing to hash with
+        assert_eq!(Pii("ursula@example.com").render(PiiMode::Hashed), "[REDACTED]");
+    }
+}
@@ -0,0 +1,113 @@
+use super::{Bucket, Decision, Limit, RateLimitError, RateLimitStore};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Past this many buckets, full ones are forgotten, then the least recently used.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Keeps buckets in the process' memory.
+///
+/// Every replica enforces the limits on its own, and they reset on restart.
+pub struct InMemoryRateLimitStore {
+    max_buckets: usize,
+    buckets: Mutex<Buckets>
+}
+
+#[derive(Default)]
+struct Buckets {
+    entries: HashMap<String, Entry>,
+    /// Bumped on every `take`, to tell which bucket was used least recently.
+    clock: u64
+}
+
+/// A bucket, with when it is full again under the limit it was taken with:
+/// keys do not all share the same limit.
+struct Entry {
+    bucket: Bucket,
+    full_at: DateTime<Utc>,
+    last_used: u64
+}
+
+impl InMemoryRateLimitStore {
+    fn with_max_buckets(max_buckets: usize) -> Self {
+        Self { max_buckets, buckets: Mutex::default() }
+    }
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        Self::with_max_buckets(MAX_BUCKETS)
+    }
+}
+
+impl Buckets {
+    /// Make room for one more bucket.
+    fn evict(&mut self, max_buckets: usize, now: DateTime<Utc>) {
+        self.entries.retain(|_, entry| entry.full_at > now);
+        while self.entries.len() >= max_buckets {
+            let least_recently_used = self.entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match least_recently_used {
+                Some(key) => self.entries.remove(&key),
+                None => break
+            };
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(&self, key: &str, limit: &Limit) -> Result<Decision, RateLimitError> {
+        let now = Utc::now();
+        let mut buckets = self.buckets.lock().map_err(|e| RateLimitError(e.to_string()))?;
+        if !buckets.entries.contains_key(key) && buckets.entries.len() >= self.max_buckets {
+            buckets.evict(self.max_buckets, now);
+        }
+        let (bucket, decision) = limit.take(buckets.entries.get(key).map(|entry| entry.bucket), now);
+        buckets.clock += 1;
+        let entry = Entry { bucket, full_at: limit.full_at(&bucket), last_used: buckets.clock };
+        buckets.entries.insert(key.to_owned(), entry);
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::{Decision, InMemoryRateLimitStore, Limit, RateLimitStore};
+    use std::time::Duration;
+
+    fn limit(refill_interval: Duration) -> Limit {
+        Limit { capacity: 1, refill_interval }
+    }
+
+    #[actix_rt::test]
+    async fn buckets_are_only_forgotten_once_full_under_their_own_limit(){
+        let store = InMemoryRateLimitStore::with_max_buckets(2);
+        let slow = limit(Duration::from_secs(3600));
+        let instant = limit(Duration::ZERO);
+
+        store.take("slow", &slow).await.unwrap();
+        store.take("instant", &instant).await.unwrap();
+        store.take("another instant", &instant).await.unwrap();
+
+        assert!(matches!(store.take("slow", &slow).await.unwrap(), Decision::Limited { .. }));
+    }
+
+    #[actix_rt::test]
+    async fn the_least_recently_used_buckets_make_room_for_new_ones(){
+        let store = InMemoryRateLimitStore::with_max_buckets(2);
+        let slow = limit(Duration::from_secs(3600));
+
+        store.take("a", &slow).await.unwrap();
+        store.take("b", &slow).await.unwrap();
+        store.take("a", &slow).await.unwrap();
+        store.take("c", &slow).await.unwrap();
+
+        assert_eq!(store.buckets.lock().unwrap().entries.len(), 2);
+        assert!(matches!(store.take("a", &slow).await.unwrap(), Decision::Limited { .. }));
+        assert_eq!(store.take("b", &slow).await.unwrap(), Decision::Allowed);
+    }
+}
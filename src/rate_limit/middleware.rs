@@ -0,0 +1,148 @@
+use super::{Decision, RateLimitError, RateLimiter};
+use crate::routes::problem_details;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage};
+use futures_util::StreamExt;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Largest body we buffer to look for the subscriber email.
+const MAX_BODY_SIZE: usize = 256 * 1024;
+
+#[derive(serde::Deserialize)]
+struct EmailField {
+    email: Option<String>
+}
+
+/// Enforce the `RateLimiter` registered as app data, by client IP first and
+/// then by the `email` field of the body (urlencoded or JSON).
+///
+/// Over the limit, requests are answered with a 429 and a `Retry-After` header.
+pub struct RateLimit;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let rate_limiter = req
+                .app_data::<web::Data<RateLimiter>>()
+                .cloned()
+                .ok_or_else(|| {
+                    actix_web::error::ErrorInternalServerError("The rate limiter is not registered")
+                })?;
+            if let Some(ip) = client_ip(&req, rate_limiter.use_forwarded_headers) {
+                enforce(rate_limiter.check_ip(&ip).await)?;
+            }
+            let body = read_body(&mut req).await?;
+            if let Some(email) = email_field(&req, &body) {
+                enforce(rate_limiter.check_email(&email).await)?;
+            }
+            // The handler still has to extract the body we just consumed
+            req.set_payload(Payload::Stream(Box::pin(futures_util::stream::once(async move {
+                Ok(body)
+            }))));
+            service.call(req).await
+        })
+    }
+}
+
+/// The peer address or, behind a proxy, the last address of `X-Forwarded-For`:
+/// the one appended by the proxy itself, which clients cannot forge.
+fn client_ip(req: &ServiceRequest, use_forwarded_headers: bool) -> Option<String> {
+    if use_forwarded_headers {
+        let forwarded_for = req
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .last();
+        if let Some(address) = forwarded_for {
+            return Some(address.to_owned());
+        }
+    }
+    req.peer_addr().map(|address| address.ip().to_string())
+}
+
+/// Turn a `Limited` decision into a 429.
+///
+/// Requests go through when the store fails: better a few extra emails than
+/// no new subscribers at all.
+fn enforce(decision: Result<Decision, RateLimitError>) -> Result<(), actix_web::Error> {
+    match decision {
+        Ok(Decision::Allowed) => Ok(()),
+        Ok(Decision::Limited { retry_after }) => {
+            let seconds = retry_after.as_secs().max(1);
+            let mut response = problem_details(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many requests",
+                format!("Try again in {} seconds.", seconds),
+                &[]
+            );
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+            Err(InternalError::from_response("Rate limit exceeded", response).into())
+        }
+        Err(e) => {
+            tracing::error!(error.message = %e, "Failed to enforce the rate limit");
+            Ok(())
+        }
+    }
+}
+
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(actix_web::error::ErrorPayloadTooLarge("The request body is too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+fn email_field(req: &ServiceRequest, body: &[u8]) -> Option<String> {
+    let field: EmailField = match req.content_type() {
+        "application/json" => serde_json::from_slice(body).ok()?,
+        "application/x-www-form-urlencoded" => serde_urlencoded::from_bytes(body).ok()?,
+        _ => return None
+    };
+    // Blank emails are rejected by the handler anyway
+    field.email.filter(|email| !email.trim().is_empty())
+}
@@ -0,0 +1,150 @@
+mod memory;
+mod middleware;
+mod postgres;
+
+pub use memory::InMemoryRateLimitStore;
+pub use middleware::RateLimit;
+pub use postgres::PostgresRateLimitStore;
+
+use crate::configuration::RateLimitSettings;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct RateLimitError(pub String);
+
+impl std::fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limit store failure: {}", self.0)
+    }
+}
+
+impl std::error::Error for RateLimitError {}
+
+/// `capacity` requests in a burst, then one every `refill_interval`.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub capacity: u32,
+    pub refill_interval: Duration
+}
+
+/// The state of a token bucket, as persisted by the stores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration }
+}
+
+impl Limit {
+    /// Take a token out of `bucket` (full if `None`), refilled up to `now`.
+    pub fn take(&self, bucket: Option<Bucket>, now: DateTime<Utc>) -> (Bucket, Decision) {
+        let capacity = f64::from(self.capacity);
+        let interval = self.refill_interval.as_secs_f64();
+        let tokens = match bucket {
+            None => capacity,
+            Some(bucket) => {
+                let elapsed = (now - bucket.updated_at).to_std().unwrap_or_default().as_secs_f64();
+                let refilled = if interval > 0.0 { elapsed / interval } else { capacity };
+                (bucket.tokens + refilled).min(capacity)
+            }
+        };
+        if tokens >= 1.0 {
+            (Bucket { tokens: tokens - 1.0, updated_at: now }, Decision::Allowed)
+        } else {
+            let retry_after = Duration::from_secs(((1.0 - tokens) * interval).ceil() as u64);
+            (Bucket { tokens, updated_at: now }, Decision::Limited { retry_after })
+        }
+    }
+
+    /// When `bucket` is full again: from then on it is as good as missing.
+    pub fn full_at(&self, bucket: &Bucket) -> DateTime<Utc> {
+        let missing = (f64::from(self.capacity) - bucket.tokens).max(0.0);
+        let refill = Duration::from_secs_f64(missing * self.refill_interval.as_secs_f64());
+        bucket.updated_at + chrono::Duration::from_std(refill).unwrap_or_else(|_| chrono::Duration::max_value())
+    }
+}
+
+/// Where token buckets live between requests.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket identified by `key`, creating it full if needed.
+    async fn take(&self, key: &str, limit: &Limit) -> Result<Decision, RateLimitError>;
+}
+
+/// Token-bucket limits on a client IP and on a subscriber email.
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    per_ip: Limit,
+    per_email: Limit,
+    use_forwarded_headers: bool
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, settings: &RateLimitSettings) -> Self {
+        Self {
+            store,
+            per_ip: settings.per_ip.limit(),
+            per_email: settings.per_email.limit(),
+            use_forwarded_headers: settings.use_forwarded_headers
+        }
+    }
+
+    pub async fn check_ip(&self, ip: &str) -> Result<Decision, RateLimitError> {
+        self.store.take(&format!("ip:{}", ip), &self.per_ip).await
+    }
+
+    /// `Asharma@SW-at.com ` and `asharma@sw-at.com` share the same bucket.
+    pub async fn check_email(&self, email: &str) -> Result<Decision, RateLimitError> {
+        let email = email.trim().to_lowercase();
+        self.store.take(&format!("email:{}", email), &self.per_email).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::{Decision, Limit};
+    use chrono::{Duration, Utc};
+
+    fn limit() -> Limit {
+        Limit { capacity: 2, refill_interval: std::time::Duration::from_secs(60) }
+    }
+
+    #[test]
+    fn a_full_bucket_allows_a_burst_of_capacity_requests(){
+        let now = Utc::now();
+        let (bucket, first) = limit().take(None, now);
+        let (bucket, second) = limit().take(Some(bucket), now);
+        let (_, third) = limit().take(Some(bucket), now);
+        assert_eq!(first, Decision::Allowed);
+        assert_eq!(second, Decision::Allowed);
+        assert_eq!(third, Decision::Limited { retry_after: std::time::Duration::from_secs(60) });
+    }
+
+    #[test]
+    fn buckets_refill_over_time_up_to_their_capacity(){
+        let now = Utc::now();
+        let (bucket, _) = limit().take(None, now);
+        let (bucket, _) = limit().take(Some(bucket), now);
+
+        let (_, decision) = limit().take(Some(bucket), now + Duration::seconds(45));
+        assert_eq!(decision, Decision::Limited { retry_after: std::time::Duration::from_secs(15) });
+
+        let (refilled, decision) = limit().take(Some(bucket), now + Duration::hours(1));
+        assert_eq!(decision, Decision::Allowed);
+        assert_eq!(refilled.tokens, 1.0);
+    }
+
+    #[test]
+    fn buckets_are_full_once_every_missing_token_is_refilled(){
+        let now = Utc::now();
+        let (bucket, _) = limit().take(None, now);
+        assert_eq!(limit().full_at(&bucket), now + Duration::seconds(60));
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Bucket, Decision, Limit, RateLimitError, RateLimitStore};
+use chrono::Utc;
+use sqlx::PgPool;
+
+/// Keeps buckets in the `rate_limit_buckets` table, shared by every replica.
+///
+/// Full buckets are deleted by the `Janitor`.
+pub struct PostgresRateLimitStore {
+    pool: PgPool
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    async fn take(&self, key: &str, limit: &Limit) -> Result<Decision, RateLimitError> {
+        let now = Utc::now();
+        let mut transaction = self.pool.begin().await.map_err(|e| RateLimitError(e.to_string()))?;
+        // Make sure there is a row to lock, even for the first request of a key
+        sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at, full_at)
+            VALUES ($1, $2, $3, $3)
+            ON CONFLICT (key) DO NOTHING
+            "#,
+            key,
+            f64::from(limit.capacity),
+            now
+        )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| RateLimitError(e.to_string()))?;
+        let bucket = sqlx::query_as!(
+            Bucket,
+            r#"SELECT tokens, updated_at FROM rate_limit_buckets WHERE key = $1 FOR UPDATE"#,
+            key
+        )
+            .fetch_one(&mut transaction)
+            .await
+            .map_err(|e| RateLimitError(e.to_string()))?;
+        let (bucket, decision) = limit.take(Some(bucket), now);
+        sqlx::query!(
+            r#"
+            UPDATE rate_limit_buckets
+            SET tokens = $2, updated_at = $3, full_at = $4
+            WHERE key = $1
+            "#,
+            key,
+            bucket.tokens,
+            bucket.updated_at,
+            limit.full_at(&bucket)
+        )
+            .execute(&mut transaction)
+            .await
+            .map_err(|e| RateLimitError(e.to_string()))?;
+        transaction.commit().await.map_err(|e| RateLimitError(e.to_string()))?;
+        Ok(decision)
+    }
+}
@@ -0,0 +1,43 @@
+use crate::authentication::{get_username, UserId};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+#[tracing::instrument(
+    name = "Show the admin dashboard",
+    skip(user_id, pool),
+    fields(user_id = %*user_id)
+)]
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>
+) -> HttpResponse {
+    let username = match get_username(user_id.0, &pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/password">Change password</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&username)
+        ))
+}
@@ -0,0 +1,25 @@
+use crate::flash_messages::flash_cookie;
+use crate::session_state::SessionManager;
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+#[tracing::instrument(
+    name = "Log out an admin",
+    skip(request, session_manager)
+)]
+pub async fn log_out(
+    request: HttpRequest,
+    session_manager: web::Data<SessionManager>
+) -> HttpResponse {
+    match session_manager.log_out(&request).await {
+        Ok(removal_cookie) => HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login"))
+            .cookie(removal_cookie)
+            .cookie(flash_cookie("You have successfully logged out."))
+            .finish(),
+        Err(e) => {
+            tracing::error!(error.message = %e, "Failed to end the session");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::authentication::{self, get_username, validate_credentials, AuthError, Credentials, UserId};
+use crate::flash_messages::{clear_flash_cookie, flash_cookie, render_flash};
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct PasswordFormData {
+    current_password: String,
+    new_password: String,
+    new_password_check: String
+}
+
+pub async fn change_password_form(request: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(clear_flash_cookie())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            render_flash(&request)
+        ))
+}
+
+#[tracing::instrument(
+    name = "Change an admin's password",
+    skip(form, pool, user_id),
+    fields(user_id = %*user_id)
+)]
+pub async fn change_password(
+    form: web::Form<PasswordFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>
+) -> HttpResponse {
+    let user_id = user_id.into_inner().0;
+    let form = form.into_inner();
+    if form.new_password != form.new_password_check {
+        return see_other_with_flash(
+            "You entered two different new passwords - the field values must match."
+        );
+    }
+    let new_password_length = form.new_password.chars().count();
+    if !(12..=128).contains(&new_password_length) {
+        return see_other_with_flash(
+            "The new password must be between 12 and 128 characters long."
+        );
+    }
+
+    let username = match get_username(user_id, &pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish()
+    };
+    let credentials = Credentials {
+        username,
+        password: form.current_password
+    };
+    match validate_credentials(credentials, &pool).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials(_)) => {
+            return see_other_with_flash("The current password is incorrect.");
+        }
+        Err(AuthError::UnexpectedError(_)) => return HttpResponse::InternalServerError().finish()
+    }
+
+    if authentication::change_password(user_id, form.new_password, &pool).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    see_other_with_flash("Your password has been changed.")
+}
+
+fn see_other_with_flash(message: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/password"))
+        .cookie(flash_cookie(message))
+        .finish()
+}
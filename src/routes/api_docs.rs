@@ -0,0 +1,31 @@
+use crate::routes::{
+    ComponentCheck, FieldError, FormData, JsonData, ProblemDetails, Readiness, Status,
+    SubscriptionAccepted
+};
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+/// OpenAPI description of `startup::PUBLIC_ROUTES`.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::routes::health_check,
+        crate::routes::ready,
+        crate::routes::subscribe,
+        crate::routes::subscribe_json,
+        crate::routes::confirm
+    ),
+    components(schemas(
+        FormData, JsonData, SubscriptionAccepted, ProblemDetails, FieldError,
+        Readiness, ComponentCheck, Status
+    )),
+    tags(
+        (name = "subscriptions", description = "Joining the newsletter."),
+        (name = "health", description = "Probes for the platform running the application.")
+    )
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,134 @@
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+
+/// Format an error followed by every error in its `source()` chain.
+///
+/// Used as the `Debug` representation of our route errors, which is what
+/// `tracing-actix-web` logs when a handler fails.
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// One invalid field of a request.
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String
+}
+
+/// Every invalid field of a request, not just the first one.
+#[derive(Debug, Default)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &'static str, message: String) {
+        self.0.push(FieldError { field, message });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// A 400 with an RFC 7807 problem details body listing the invalid fields.
+    pub fn problem_details(&self) -> HttpResponse {
+        problem_details(StatusCode::BAD_REQUEST, "Invalid request", self.to_string(), &self.0)
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<_> = self.0
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        write!(f, "{}", errors.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub(crate) struct ProblemDetails<'a> {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [FieldError]
+}
+
+/// Build an `application/problem+json` response (RFC 7807).
+pub fn problem_details(
+    status: StatusCode,
+    title: &'static str,
+    detail: String,
+    errors: &[FieldError]
+) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/problem+json")
+        .json(ProblemDetails {
+            problem_type: "about:blank",
+            title,
+            status: status.as_u16(),
+            detail,
+            errors
+        })
+}
+
+/// Report a JSON body that cannot be parsed as problem details, like field errors.
+pub fn json_error_handler(error: JsonPayloadError, _request: &HttpRequest) -> actix_web::Error {
+    let (status, title) = match error {
+        JsonPayloadError::ContentType => (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type"),
+        _ => (error.status_code(), "Malformed request body")
+    };
+    let response = problem_details(status, title, error.to_string(), &[]);
+    InternalError::from_response(error, response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::error_chain_fmt;
+
+    #[derive(Debug)]
+    struct Outer(std::io::Error);
+
+    impl std::fmt::Display for Outer {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Failed to do something")
+        }
+    }
+
+    impl std::error::Error for Outer {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    struct Chain(Outer);
+
+    impl std::fmt::Display for Chain {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            error_chain_fmt(&self.0, f)
+        }
+    }
+
+    #[test]
+    fn the_whole_source_chain_is_formatted(){
+        let error = Outer(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read-only file system"));
+        let formatted = Chain(error).to_string();
+        assert!(formatted.starts_with("Failed to do something\n"));
+        assert!(formatted.contains("Caused by:\n\tread-only file system"));
+    }
+}
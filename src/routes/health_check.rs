@@ -1,5 +1,105 @@
-use actix_web::{HttpResponse,  Responder};
+use crate::email_client::EmailClient;
+use crate::startup::ReadinessChecks;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
 
+/// Liveness: the process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "health",
+    responses((status = 200, description = "The process is up."))
+)]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok()
-}
\ No newline at end of file
+}
+
+/// A component that does not answer within this delay is reported as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone, Copy, PartialEq, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Up,
+    Down
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub(crate) struct ComponentCheck {
+    status: Status,
+    latency_ms: u64
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub(crate) struct Readiness {
+    status: Status,
+    checks: BTreeMap<&'static str, ComponentCheck>
+}
+
+/// Readiness: every dependency needed to serve requests is reachable.
+///
+/// Answers 503 when one of them is down, so that the platform stops routing
+/// traffic to this instance. Failures are logged, never returned.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every dependency is reachable.", body = Readiness),
+        (status = 503, description = "At least one dependency is down.", body = Readiness)
+    )
+)]
+#[tracing::instrument(name = "Check readiness", skip(pool, email_client, readiness_checks))]
+pub async fn ready(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    readiness_checks: web::Data<ReadinessChecks>
+) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert(
+        "database",
+        check("database", async {
+            sqlx::query("SELECT 1").execute(pool.get_ref()).await.map(|_| ())
+        }).await
+    );
+    if readiness_checks.email_provider {
+        checks.insert("email_provider", check("email_provider", email_client.ping()).await);
+    }
+
+    let status = if checks.values().all(|c| c.status == Status::Up) {
+        Status::Up
+    } else {
+        Status::Down
+    };
+    let mut response = match status {
+        Status::Up => HttpResponse::Ok(),
+        Status::Down => HttpResponse::ServiceUnavailable()
+    };
+    response.json(Readiness { status, checks })
+}
+
+async fn check<E: std::fmt::Display>(
+    component: &'static str,
+    probe: impl Future<Output = Result<(), E>>
+) -> ComponentCheck {
+    let started_at = Instant::now();
+    let status = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(Ok(())) => Status::Up,
+        Ok(Err(e)) => {
+            tracing::warn!(error.message = %e, component, "Readiness check failed");
+            Status::Down
+        }
+        Err(_) => {
+            tracing::warn!(component, "Readiness check timed out");
+            Status::Down
+        }
+    };
+    ComponentCheck {
+        status,
+        latency_ms: started_at.elapsed().as_millis() as u64
+    }
+}
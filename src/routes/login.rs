@@ -0,0 +1,82 @@
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::flash_messages::{clear_flash_cookie, flash_cookie, render_flash};
+use crate::session_state::SessionManager;
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: String
+}
+
+pub async fn login_form(request: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(clear_flash_cookie())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+            render_flash(&request)
+        ))
+}
+
+#[tracing::instrument(
+    name = "Log in an admin",
+    skip(form, pool, session_manager),
+    fields(username = %form.username, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    pool: web::Data<PgPool>,
+    session_manager: web::Data<SessionManager>
+) -> HttpResponse {
+    let form = form.into_inner();
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password
+    };
+    let user_id = match validate_credentials(credentials, &pool).await {
+        Ok(user_id) => user_id,
+        Err(AuthError::InvalidCredentials(e)) => {
+            tracing::warn!(error.message = %e, "Failed login attempt");
+            return HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/login"))
+                .cookie(flash_cookie("Authentication failed"))
+                .finish();
+        }
+        Err(AuthError::UnexpectedError(e)) => {
+            tracing::error!(error.message = %e, "Failed to validate credentials");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    match session_manager.log_in(user_id).await {
+        Ok(session_cookie) => HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/admin/dashboard"))
+            .cookie(session_cookie)
+            .finish(),
+        Err(e) => {
+            tracing::error!(error.message = %e, "Failed to start a session");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
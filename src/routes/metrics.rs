@@ -0,0 +1,47 @@
+use crate::csrf::constant_time_eq;
+use crate::in_flight::InFlightRequests;
+use crate::metrics::Metrics;
+use crate::startup::MetricsToken;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+/// Expose every metric in the Prometheus text format, to scrapers presenting
+/// the `MetricsToken` as a bearer token.
+///
+/// Not served at all when no token is configured.
+pub async fn export_metrics(
+    request: HttpRequest,
+    metrics: web::Data<Metrics>,
+    metrics_token: web::Data<MetricsToken>,
+    in_flight: web::Data<InFlightRequests>,
+    pool: web::Data<PgPool>
+) -> HttpResponse {
+    let expected = match &metrics_token.0 {
+        Some(token) => token.expose_secret(),
+        None => return HttpResponse::NotFound().finish()
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !matches!(presented, Some(token) if constant_time_eq(token, expected)) {
+        let mut response = HttpResponse::Unauthorized().finish();
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static(r#"Bearer realm="metrics""#)
+        );
+        return response;
+    }
+    match metrics.render(&pool, &in_flight).await {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body),
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to render metrics");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,7 +1,23 @@
+mod admin;
+mod api_docs;
+mod errors;
 pub mod health_check;
+mod login;
+mod metrics;
+mod newsletters;
+mod subscribe_page;
 pub mod subscriptions;
 mod subscription_confirm;
+mod unsubscribe;
 
+pub use admin::*;
+pub use api_docs::*;
+pub use errors::*;
 pub use health_check::*;
+pub use login::*;
+pub use metrics::*;
+pub use newsletters::*;
+pub use subscribe_page::*;
 pub use subscriptions::*;
-pub use subscription_confirm::*;
\ No newline at end of file
+pub use subscription_confirm::*;
+pub use unsubscribe::*;
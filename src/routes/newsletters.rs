@@ -0,0 +1,139 @@
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::idempotency::run_idempotently;
+use crate::authentication::AdminUser;
+use crate::routes::error_chain_fmt;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct BodyData {
+    title: String,
+    content: Content
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct Content {
+    html: String,
+    text: String
+}
+
+/// Store the issue and queue one delivery per confirmed subscriber.
+///
+/// Emails are sent by `issue_delivery_worker`, not by this handler.
+/// Only authenticated admins can publish.
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(request, body, pool, user),
+    fields(user_id = %user.user_id)
+)]
+pub async fn publish_newsletter(
+    user: AdminUser,
+    request: HttpRequest,
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>
+) -> HttpResponse {
+    let body = &body.0;
+    run_idempotently(&pool, &request, body, |mut transaction| async move {
+        let response = enqueue_newsletter_issue(&mut transaction, body)
+            .await
+            .unwrap_or_else(HttpResponse::from_error);
+        (transaction, response)
+    }).await
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+async fn enqueue_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData
+) -> Result<HttpResponse, PublishError> {
+    let issue_id = insert_newsletter_issue(transaction, body)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+    enqueue_delivery_tasks(transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[tracing::instrument(
+    name = "Saving newsletter issue details in the database",
+    skip(transaction, body)
+)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    body: &BodyData
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        Utc::now()
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(
+    name = "Queue newsletter issue delivery to confirmed subscribers",
+    skip(transaction)
+)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crate::bot_protection::{BotCheckError, BotProtection, CaptchaWidget};
+use crate::configuration::CaptchaProvider;
+use crate::csrf::{csrf_token, verify_csrf_token};
+use crate::email_client::EmailClient;
+use crate::email_templates::EmailTemplates;
+use crate::flash_messages::{clear_flash_cookie, flash_cookie, render_flash};
+use crate::pii::Pii;
+use crate::routes::subscriptions::{
+    passes_bot_check, process_subscription, ConfirmationLinks, SubscribeError, SubscribeForm
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret, SubscriptionTokenTtl};
+use actix_web::http::header::{ContentType, LOCATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+const CHECK_YOUR_INBOX: &str = "/subscriptions/check-your-inbox";
+
+/// The subscribe form, as posted by `home`.
+#[derive(serde::Deserialize)]
+pub struct SubscribePageData {
+    #[serde(default)]
+    csrf_token: String,
+    #[serde(flatten)]
+    subscription: SubscribeForm
+}
+
+/// The subscribe form, with whatever the bot protection needs to tell humans apart.
+pub async fn home(request: HttpRequest, bot_protection: web::Data<BotProtection>) -> HttpResponse {
+    let (csrf_token, csrf_cookie) = csrf_token(&request);
+    let form_token = bot_protection.issue_form_token(&csrf_token);
+    let captcha = bot_protection.captcha_widget().map(render_captcha).unwrap_or_default();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .cookie(csrf_cookie)
+        .cookie(clear_flash_cookie())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribe</title>
+</head>
+<body>
+    <h1>Subscribe to our newsletter</h1>
+    {}
+    <form action="/subscribe" method="post">
+        <label>Name
+            <input type="text" placeholder="Enter your name" name="name" required>
+        </label>
+        <br>
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email" required>
+        </label>
+        <br>
+        <div aria-hidden="true" style="position: absolute; left: -10000px;">
+            <label>Leave this field empty
+                <input type="text" name="website" tabindex="-1" autocomplete="off">
+            </label>
+        </div>
+        <input type="hidden" name="form_token" value="{}">
+        <input type="hidden" name="csrf_token" value="{}">
+        {}
+        <button type="submit">Subscribe</button>
+    </form>
+</body>
+</html>"#,
+            render_flash(&request),
+            htmlescape::encode_attribute(form_token.as_ref()),
+            htmlescape::encode_attribute(&csrf_token),
+            captcha
+        ))
+}
+
+fn render_captcha(widget: &CaptchaWidget) -> String {
+    let (script, class) = match widget.provider {
+        CaptchaProvider::HCaptcha => ("https://js.hcaptcha.com/1/api.js", "h-captcha"),
+        CaptchaProvider::Turnstile => ("https://challenges.cloudflare.com/turnstile/v0/api.js", "cf-turnstile")
+    };
+    format!(
+        r#"<script src="{}" async defer></script>
+        <div class="{}" data-sitekey="{}"></div>"#,
+        script,
+        class,
+        htmlescape::encode_attribute(&widget.site_key)
+    )
+}
+
+/// Handle the form served by `home`, then redirect to the "check your inbox"
+/// page, or back to the form with what went wrong.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name= "Adding a new Subscriber from the subscribe page",
+    skip(request, form, pool, email_client, templates, base_url, hmac_secret, token_ttl, bot_protection),
+    fields(
+        subscriber_email = %Pii(&form.subscription.subscriber.email),
+        subscriber_name = %Pii(&form.subscription.subscriber.name)
+    )
+)]
+pub async fn subscribe_from_page(
+    request: HttpRequest,
+    form: web::Form<SubscribePageData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    bot_protection: web::Data<BotProtection>
+) -> HttpResponse {
+    let SubscribePageData { csrf_token, subscription: SubscribeForm { subscriber, bot_check } } = form.into_inner();
+    if !verify_csrf_token(&request, &csrf_token) {
+        tracing::warn!("Rejected a subscription with a missing or invalid CSRF token");
+        return form_expired();
+    }
+    match passes_bot_check(&bot_protection, &bot_check, Some(&csrf_token)).await {
+        Ok(true) => {}
+        Ok(false) => return see_other(CHECK_YOUR_INBOX),
+        Err(BotCheckError::Captcha(e)) => {
+            tracing::error!(error.message = %e, "Failed to verify the CAPTCHA");
+            return HttpResponse::InternalServerError().finish();
+        }
+        Err(e) => return back_to_form(&e.to_string())
+    }
+    let links = ConfirmationLinks { base_url: &base_url.0, hmac_secret: hmac_secret.0.expose_secret() };
+    let subscribed = async {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        process_subscription(subscriber, &mut transaction, &email_client, &templates, links, token_ttl.0).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        Ok::<(), SubscribeError>(())
+    };
+    match subscribed.await {
+        Ok(()) => see_other(CHECK_YOUR_INBOX),
+        Err(SubscribeError::ValidationError(errors)) => back_to_form(&errors.to_string()),
+        Err(e) => {
+            tracing::error!(error.message = ?e, "Failed to subscribe from the subscribe page");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub async fn check_your_inbox() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Check your inbox</title>
+</head>
+<body>
+    <h1>Check your inbox</h1>
+    <p>We have sent you an email: follow the link it contains to confirm your subscription.</p>
+</body>
+</html>"#
+        )
+}
+
+/// The CSRF check failed: most likely the cookie expired with the browser session.
+fn form_expired() -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Form expired</title>
+</head>
+<body>
+    <p>This form has expired. <a href="/">Reload it</a> and try again.</p>
+</body>
+</html>"#
+        )
+}
+
+fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+fn back_to_form(message: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/"))
+        .cookie(flash_cookie(message))
+        .finish()
+}
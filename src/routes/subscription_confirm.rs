@@ -1,14 +1,189 @@
-use actix_web::{HttpResponse, web};
+use crate::routes::error_chain_fmt;
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::error::{InternalError, QueryPayloadError};
+use actix_web::{HttpRequest, HttpResponse, ResponseError, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters{
+    /// The token embedded in the confirmation link.
     subscription_token: String
 }
 
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscriber is confirmed.", content_type = "text/html"),
+        (status = 400, description = "The token is missing.", content_type = "text/html"),
+        (status = 401, description = "The token is unknown.", content_type = "text/html"),
+        (status = 410, description = "The token has expired.", content_type = "text/html")
+    )
+)]
 #[tracing::instrument(
     name="Confirm a pending subscriber",
-    skip(_parameters)
+    skip(parameters, pool)
 )]
-pub async fn confirm(_parameters: web::Query<Parameters>) -> HttpResponse {
-    HttpResponse::Ok().finish()
-}
\ No newline at end of file
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, ConfirmError> {
+    let token = get_token(&pool, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscriber id associated with the provided token.")?
+        .ok_or(ConfirmError::UnknownToken)?;
+    if token.expires_at < Utc::now() {
+        return Err(ConfirmError::ExpiredToken);
+    }
+    let subscriber_id = token.subscriber_id;
+    let confirmed = confirm_subscriber(pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?;
+    if !confirmed {
+        tracing::info!("The subscriber was not pending confirmation anymore");
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(confirmation_page(
+                "Nothing to confirm",
+                "This confirmation link has already been used."
+            )));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(confirmation_page(
+            "Subscription confirmed",
+            "Your subscription is confirmed: the next issue of our newsletter will be in your inbox."
+        )))
+}
+
+/// Answer a confirmation link without its token like one with an unknown token.
+pub fn missing_token_handler(error: QueryPayloadError, _request: &HttpRequest) -> actix_web::Error {
+    InternalError::from_response(error, invalid_link(StatusCode::BAD_REQUEST)).into()
+}
+
+fn invalid_link(status: StatusCode) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(confirmation_page(
+            "Invalid link",
+            "This confirmation link is invalid. Check that you copied it in full, or <a href=\"/\">subscribe again</a>."
+        ))
+}
+
+/// `message` is embedded as is: it must not contain anything user-provided.
+fn confirmation_page(title: &str, message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <h1>{}</h1>
+    <p>{}</p>
+</body>
+</html>"#,
+        title,
+        title,
+        message
+    )
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The provided token has expired.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::ExpiredToken => StatusCode::GONE,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfirmError::UnknownToken => invalid_link(self.status_code()),
+            ConfirmError::ExpiredToken => HttpResponse::Gone()
+                .content_type(ContentType::html())
+                .body(confirmation_page(
+                    "Link expired",
+                    "This confirmation link has expired. Subscribe again to request a new one: <a href=\"/\">back to the subscribe form</a>."
+                )),
+            ConfirmError::UnexpectedError(_) => HttpResponse::new(self.status_code())
+        }
+    }
+}
+
+/// `false` if the subscriber was not pending confirmation: confirmed
+/// subscribers stay as they are, and unsubscribed ones are not opted back in.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, executor)
+)]
+pub async fn confirm_subscriber(
+    executor: impl Executor<'_, Database = Postgres>,
+    subscriber_id: Uuid
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+    )
+        .execute(executor)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub struct StoredToken {
+    pub subscriber_id: Uuid,
+    pub expires_at: DateTime<Utc>
+}
+
+#[tracing::instrument(
+    name = "Get subscriber_id from token",
+    skip(subscription_token, pool)
+)]
+pub async fn get_token(
+    pool: &PgPool,
+    subscription_token: &str
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredToken,
+        r#"SELECT subscriber_id, expires_at FROM subscription_tokens WHERE subscription_token = $1"#,
+        subscription_token,
+    )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
@@ -1,117 +1,561 @@
-use actix_web::{web,HttpResponse,  Responder};
-use serde::{Deserialize};
-use sqlx::PgPool;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
 use chrono::Utc;
 use uuid::Uuid;
-use crate::domain::{NewSubsciber, SubscriberName, SubscriberEmail};
+use crate::domain::{NewSubsciber, SubscriberName, SubscriberEmail, SubscriptionId, UnsubscribeToken};
 use std::convert::TryInto;
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
+use crate::email_templates::{ConfirmationEmail, EmailTemplates};
+use crate::startup::{ApplicationBaseUrl, HmacSecret, SubscriptionTokenTtl};
+use crate::idempotency::run_idempotently;
+use crate::routes::{error_chain_fmt, problem_details, ValidationErrors};
+use crate::bot_protection::{BotCheckError, BotProtection, Submission};
+use crate::csrf::read_csrf_cookie;
+use rand::{thread_rng, Rng};
+use rand::distributions::Alphanumeric;
+use secrecy::ExposeSecret;
+use crate::pii::Pii;
 
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct FormData{
+    pub(crate) email: String,
+    pub(crate) name: String
+}
+
+/// What the subscribe form sends for `BotProtection`.
+///
+/// Kept apart from `FormData`, which is published in the OpenAPI spec: a
+/// honeypot documented there is one every bot knows to leave blank.
+#[derive(Deserialize)]
+pub struct BotCheckFields {
+    // Hidden from humans, so it must stay empty
+    #[serde(default)]
+    website: String,
+    // Issued with the form
+    form_token: Option<String>,
+    // Added to the form by the hCaptcha or Turnstile widget
+    #[serde(alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    captcha_response: Option<String>
+}
+
+/// The subscribe form, as posted to `/subscriptions`.
+#[derive(Deserialize)]
+pub struct SubscribeForm {
+    #[serde(flatten)]
+    pub(crate) subscriber: FormData,
+    #[serde(flatten)]
+    pub(crate) bot_check: BotCheckFields
+}
+
+/// The body of `POST /api/v1/subscriptions`.
+///
+/// Missing fields are validated like empty ones, so that they are reported
+/// as field errors rather than as a malformed body.
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct JsonData{
+    #[serde(default)]
     email: String,
-    name: String
+    #[serde(default)]
+    name: String,
+    /// Required when a CAPTCHA is configured, see `BotProtection::check_captcha`.
+    #[serde(alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    captcha_response: Option<String>
+}
+
+impl From<JsonData> for FormData {
+    fn from(data: JsonData) -> Self {
+        Self {
+            email: data.email,
+            name: data.name
+        }
+    }
 }
 
 impl TryInto<NewSubsciber> for FormData {
-    type Error = String;
+    type Error = ValidationErrors;
 
     fn try_into(self) -> Result<NewSubsciber, Self::Error> {
-        let name = SubscriberName::parse(self.name)?;
-        let email = SubscriberEmail::parse(self.email)?;
-        Ok(NewSubsciber{email, name})
+        let mut errors = ValidationErrors::default();
+        let name = SubscriberName::parse(self.name)
+            .map_err(|e| errors.add("name", e))
+            .ok();
+        let email = SubscriberEmail::parse(self.email)
+            .map_err(|e| errors.add("email", e))
+            .ok();
+        match (name, email) {
+            (Some(name), Some(email)) => Ok(NewSubsciber{email, name}),
+            _ => Err(errors)
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error("{0}")]
+    ValidationError(ValidationErrors),
+    #[error(transparent)]
+    BotCheckFailed(BotCheckError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+
+impl From<BotCheckError> for SubscribeError {
+    fn from(e: BotCheckError) -> Self {
+        match e {
+            // Not the submitter's fault
+            BotCheckError::Captcha(_) => SubscribeError::UnexpectedError(anyhow::Error::new(e)),
+            e => SubscribeError::BotCheckFailed(e)
+        }
+    }
+}
+
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::BotCheckFailed(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(errors) => errors.problem_details(),
+            SubscribeError::BotCheckFailed(e) => problem_details(
+                StatusCode::BAD_REQUEST,
+                "Submission rejected",
+                e.to_string(),
+                &[]
+            ),
+            SubscribeError::UnexpectedError(_) => HttpResponse::InternalServerError().finish()
+        }
     }
 }
 
 
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replaying a request with the same key returns the first response.")
+    ),
+    responses(
+        (status = 200, description = "A confirmation email is on its way, unless the subscriber was already confirmed."),
+        (status = 400, description = "Some fields are invalid, or the submission looks automated.", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name= "Adding a new Subscriber",
-    skip(form, pool),
+    skip(request, form, pool, templates, hmac_secret, token_ttl, bot_protection),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = %Pii(&form.subscriber.email),
+        subscriber_name = %Pii(&form.subscriber.name)
     )
 )]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    form: web::Form<SubscribeForm>,
     pool:web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    bot_protection: web::Data<BotProtection>
 ) -> impl Responder {
+    let csrf_token = read_csrf_cookie(&request);
+    let SubscribeForm { subscriber, bot_check } = form.into_inner();
+    match passes_bot_check(&bot_protection, &bot_check, csrf_token.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Ok().finish(),
+        Err(e) => return HttpResponse::from_error(SubscribeError::from(e))
+    }
+    let links = ConfirmationLinks { base_url: &base_url.0, hmac_secret: hmac_secret.0.expose_secret() };
+    let (email_client, templates, token_ttl) = (&email_client, &templates, token_ttl.0);
+    run_idempotently(
+        &pool,
+        &request,
+        &subscriber.clone(),
+        |mut transaction| async move {
+            let response = process_subscription(subscriber, &mut transaction, email_client, templates, links, token_ttl)
+                .await
+                .map(|_| HttpResponse::Ok().finish())
+                .unwrap_or_else(HttpResponse::from_error);
+            (transaction, response)
+        }
+    ).await
+}
 
-    let new_subscriber = match form.0.try_into() {
-        Ok(new_subscriber) => new_subscriber,
-        Err(_) => return HttpResponse::BadRequest().finish(),
+/// Run the anti-bot checks on a form submission, sent along with the
+/// `csrf_token` cookie the form token was issued for.
+///
+/// `Ok(false)` if the honeypot caught a bot: it must get the same answer as
+/// a human, so that nothing tells it it was caught.
+pub(crate) async fn passes_bot_check(
+    bot_protection: &BotProtection,
+    fields: &BotCheckFields,
+    csrf_token: Option<&str>
+) -> Result<bool, BotCheckError> {
+    let submission = Submission {
+        honeypot: &fields.website,
+        form_token: fields.form_token.as_deref(),
+        csrf_token,
+        captcha_response: fields.captcha_response.as_deref()
     };
+    match bot_protection.check(&submission).await {
+        Ok(()) => Ok(true),
+        Err(BotCheckError::Honeypot) => {
+            tracing::info!("Ignoring a subscription with the honeypot field filled in");
+            Ok(false)
+        }
+        Err(e) => Err(e)
+    }
+}
 
-    if insert_subscriber(&new_subscriber, &pool).await.is_err() {
+/// The body of every successful `POST /api/v1/subscriptions`.
+///
+/// Whether the email was new, pending or already confirmed, the answer is the
+/// same: neither the id nor the status may tell who is already on the list.
+#[derive(Serialize, utoipa::ToSchema)]
+pub(crate) struct SubscriptionAccepted {
+    /// The same for every subscription with this email, see `SubscriptionId`.
+    #[schema(example = "0b5f4c4e-3a41-4bd4-9c8e-2f7d6a1c9e30")]
+    id: Uuid,
+    /// Always `pending_confirmation`, until the link in the email is followed.
+    #[schema(example = "pending_confirmation")]
+    status: &'static str
+}
 
-        return HttpResponse::InternalServerError().finish()
+/// Same as `subscribe`, for clients speaking JSON.
+///
+/// API clients never load the form, so the only bot check that applies to
+/// them is the CAPTCHA: without one configured, this route is only guarded
+/// by the rate limits.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions",
+    tag = "subscriptions",
+    request_body = JsonData,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replaying a request with the same key returns the first response.")
+    ),
+    responses(
+        (status = 200, description = "A confirmation email is on its way, unless the subscriber was already confirmed.", body = SubscriptionAccepted),
+        (status = 400, description = "Some fields are invalid, the CAPTCHA was not solved, or the body is not JSON.", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "The body is not `application/json`.", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The `Idempotency-Key` was already used for a different subscription.", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name= "Adding a new Subscriber through the JSON API",
+    skip(request, body, pool, templates, hmac_secret, token_ttl, bot_protection),
+    fields(
+        subscriber_email = %Pii(&body.email),
+        subscriber_name = %Pii(&body.name)
+    )
+)]
+pub async fn subscribe_json(
+    request: HttpRequest,
+    body: web::Json<JsonData>,
+    pool:web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    bot_protection: web::Data<BotProtection>
+) -> impl Responder {
+    if let Err(e) = bot_protection.check_captcha(body.captcha_response.as_deref()).await {
+        return HttpResponse::from_error(SubscribeError::from(e));
     }
+    let links = ConfirmationLinks { base_url: &base_url.0, hmac_secret: hmac_secret.0.expose_secret() };
+    let (email_client, templates, token_ttl) = (&email_client, &templates, token_ttl.0);
+    // The CAPTCHA response changes on every retry: it is not part of the request
+    let subscriber = FormData::from(body.into_inner());
+    run_idempotently(
+        &pool,
+        &request,
+        &subscriber.clone(),
+        |mut transaction| async move {
+            let response = process_subscription(subscriber, &mut transaction, email_client, templates, links, token_ttl)
+                .await
+                .map(|id| HttpResponse::Ok().json(SubscriptionAccepted {
+                    id: *id.as_ref(),
+                    status: "pending_confirmation"
+                }))
+                .unwrap_or_else(HttpResponse::from_error);
+            (transaction, response)
+        }
+    ).await
+}
 
+/// What is needed to build the links embedded in a confirmation email.
+#[derive(Clone, Copy)]
+pub struct ConfirmationLinks<'a> {
+    pub base_url: &'a str,
+    pub hmac_secret: &'a str
+}
 
-    if send_confirmation_email(&email_client, new_subscriber, &base_url.into_inner().0)
+/// Store the subscriber and its token on `transaction`, then send the
+/// confirmation email.
+///
+/// The caller commits: the subscriber and its token are stored together, or
+/// not at all if the email could not be sent.
+pub(crate) async fn process_subscription(
+    form: FormData,
+    transaction: &mut Transaction<'_, Postgres>,
+    email_client: &EmailClient,
+    templates: &EmailTemplates,
+    links: ConfirmationLinks<'_>,
+    token_ttl: std::time::Duration
+) -> Result<SubscriptionId, SubscribeError> {
+    let new_subscriber: NewSubsciber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let subscription_id = SubscriptionId::for_email(&new_subscriber.email, links.hmac_secret);
+    let inserted = insert_subscriber(transaction, &new_subscriber)
         .await
-        .is_err()
-    {
-
-        return HttpResponse::InternalServerError().finish()
-    }
-    HttpResponse::Ok().finish()
+        .context("Failed to insert new subscriber in the database.")?;
+    let subscriber_id = match inserted {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let existing = get_subscriber_by_email(transaction, &new_subscriber.email)
+                .await
+                .context("Failed to retrieve an existing subscriber.")?
+                .context("The existing subscriber disappeared while subscribing.")?;
+            match existing.status.as_str() {
+                // Same answer as for a brand new subscriber: the caller must
+                // not learn who is already on the list.
+                "confirmed" => return Ok(subscription_id),
+                "unsubscribed" => {
+                    resubscribe(transaction, existing.id, &new_subscriber)
+                        .await
+                        .context("Failed to opt an unsubscribed subscriber back in.")?;
+                }
+                _ => {}
+            }
+            // Links from earlier confirmation emails stop working
+            delete_tokens(transaction, existing.id)
+                .await
+                .context("Failed to delete the previous confirmation tokens.")?;
+            existing.id
+        }
+    };
+    let subscription_token = generate_subscription_token();
+    store_token(transaction, subscriber_id, &subscription_token, token_ttl)
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    send_confirmation_email(
+        email_client,
+        templates,
+        new_subscriber,
+        subscriber_id,
+        links,
+        &subscription_token
+    )
+        .await
+        .context("Failed to send a confirmation email.")?;
+    Ok(subscription_id)
 }
 
 #[tracing::instrument(
     name= "Send a conformation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, templates, new_subscriber, links, subscription_token)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    templates: &EmailTemplates,
     new_subscriber: NewSubsciber,
-    base_url: &str
-) -> Result<(), reqwest::Error>{
-    let confirmation_link = format!("{}/subscriptions/confirm?subscription_token=mytoken", base_url);
+    subscriber_id: Uuid,
+    links: ConfirmationLinks<'_>,
+    subscription_token: &str
+) -> Result<(), anyhow::Error>{
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        links.base_url,
+        subscription_token
+    );
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        links.base_url,
+        UnsubscribeToken::sign(subscriber_id, links.hmac_secret).as_ref()
+    );
+    let body = templates.render(&ConfirmationEmail {
+        subscriber_name: new_subscriber.name.as_ref(),
+        confirmation_link: &confirmation_link,
+        unsubscribe_link: &unsubscribe_link
+    })
+        .context("Failed to render the confirmation email.")?;
     email_client
-        .send_email(
-            new_subscriber.email,
-            "Welcome!",
-            &format!(
-                "Welcome to our newsletter!<br />
-                Click <a href=\"{}\">here</a> to confirm your subscription.",
-                confirmation_link
-            ),
-            &format!(
-                "Welcome to our newsletter!\nVist {} to confirm your subscription.",
-                confirmation_link
-            )
-        )
-        .await
+        .send_email(new_subscriber.email, "Welcome!", &body.html, &body.text)
+        .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name= "Saving new Subscriber details in the database",
-    skip(new_subscriber, pool)
+    skip(new_subscriber, transaction)
 )]
+/// `None` if a subscriber with the same email already exists.
 pub async fn insert_subscriber(
-    new_subscriber: &NewSubsciber,
-    pool: &PgPool
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubsciber
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+    let result = sqlx::query!(
         r#"
         INSERT INTO subscriptions(id, email, name, subscribed_at, status)
         VALUES($1, $2, $3, $4,'pending_confirmation')
+        ON CONFLICT (email) DO NOTHING
         "#,
-        Uuid::new_v4(),
+        subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-        .execute(pool)
+        .execute(transaction)
         .await
         .map_err(|e| {
             tracing::error!("Failed to execute query: {:?}", e); // this is outside query span
             e
         })?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(subscriber_id))
+}
+
+pub struct ExistingSubscriber {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String
+}
+
+/// Look up a subscriber by email, locking the row until the transaction ends.
+#[tracing::instrument(
+    name= "Get existing subscriber by email",
+    skip(transaction, email)
+)]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, name, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref()
+    )
+        .fetch_optional(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+/// Put an unsubscribed subscriber back in `pending_confirmation`: they have
+/// to confirm again, exactly like a new subscriber.
+#[tracing::instrument(
+    name= "Opt an unsubscribed subscriber back in",
+    skip(transaction, new_subscriber)
+)]
+pub async fn resubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_subscriber: &NewSubsciber
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, subscribed_at = $3, status = 'pending_confirmation'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        new_subscriber.name.as_ref(),
+        Utc::now()
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
     Ok(())
+}
+
+#[tracing::instrument(
+    name= "Delete the confirmation tokens of a subscriber",
+    skip(transaction)
+)]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name= "Store subscription token in the database",
+    skip(subscription_token, transaction)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+    ttl: std::time::Duration
+) -> Result<(), anyhow::Error> {
+    let created_at = Utc::now();
+    // `Settings::validate` bounds the TTL: this only guards against overflows
+    let expires_at = chrono::Duration::from_std(ttl)
+        .ok()
+        .and_then(|ttl| created_at.checked_add_signed(ttl))
+        .with_context(|| format!("A token TTL of {:?} is out of range.", ttl))?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens(subscription_token, subscriber_id, created_at, expires_at)
+        VALUES($1, $2, $3, $4)
+        "#,
+        subscription_token,
+        subscriber_id,
+        created_at,
+        expires_at
+    )
+        .execute(transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+/// Generate a random 25-characters-long case-sensitive subscription token.
+pub fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+use super::{SessionError, SessionState, SessionStore};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keeps sessions in the process' memory.
+///
+/// Sessions do not survive restarts and are not shared across replicas:
+/// meant for tests and local development.
+#[derive(Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, (SessionState, Instant)>>
+}
+
+#[async_trait::async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn load(&self, session_id: &str) -> Result<Option<SessionState>, SessionError> {
+        let mut sessions = self.sessions.lock().map_err(|e| SessionError(e.to_string()))?;
+        match sessions.get(session_id) {
+            Some((state, expires_at)) if *expires_at > Instant::now() => Ok(Some(state.clone())),
+            Some(_) => {
+                sessions.remove(session_id);
+                Ok(None)
+            }
+            None => Ok(None)
+        }
+    }
+
+    async fn save(&self, session_id: &str, state: &SessionState, ttl: Duration) -> Result<(), SessionError> {
+        self.sessions
+            .lock()
+            .map_err(|e| SessionError(e.to_string()))?
+            .insert(session_id.to_owned(), (state.clone(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), SessionError> {
+        self.sessions
+            .lock()
+            .map_err(|e| SessionError(e.to_string()))?
+            .remove(session_id);
+        Ok(())
+    }
+}
+
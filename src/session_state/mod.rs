@@ -0,0 +1,104 @@
+mod memory;
+mod postgres;
+
+pub use memory::InMemorySessionStore;
+pub use postgres::PostgresSessionStore;
+
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::HttpRequest;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Key/value pairs attached to a session.
+pub type SessionState = HashMap<String, String>;
+
+#[derive(Debug)]
+pub struct SessionError(pub String);
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Session store failure: {}", self.0)
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Where session state lives between requests.
+///
+/// Only the session id travels in the cookie; the state stays server-side.
+#[async_trait::async_trait]
+pub trait SessionStore: Send + Sync {
+    /// `None` if the session does not exist or has expired.
+    async fn load(&self, session_id: &str) -> Result<Option<SessionState>, SessionError>;
+    async fn save(&self, session_id: &str, state: &SessionState, ttl: Duration) -> Result<(), SessionError>;
+    async fn delete(&self, session_id: &str) -> Result<(), SessionError>;
+}
+
+const SESSION_COOKIE: &str = "id";
+const USER_ID_KEY: &str = "user_id";
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60 * 12);
+
+/// Issues, reads and revokes admin sessions on top of a `SessionStore`.
+pub struct SessionManager {
+    store: Arc<dyn SessionStore>
+}
+
+impl SessionManager {
+    pub fn new(store: Arc<dyn SessionStore>) -> Self {
+        Self { store }
+    }
+
+    /// The user logged into the session referenced by the request's cookie, if any.
+    pub async fn get_user_id(&self, request: &HttpRequest) -> Result<Option<Uuid>, SessionError> {
+        let session_id = match request.cookie(SESSION_COOKIE) {
+            Some(cookie) => cookie.value().to_owned(),
+            None => return Ok(None)
+        };
+        let state = match self.store.load(&session_id).await? {
+            Some(state) => state,
+            None => return Ok(None)
+        };
+        Ok(state.get(USER_ID_KEY).and_then(|user_id| Uuid::parse_str(user_id).ok()))
+    }
+
+    /// Start a brand new session for `user_id` and return the cookie pointing to it.
+    ///
+    /// A fresh id is generated on every login to prevent session fixation.
+    pub async fn log_in(&self, user_id: Uuid) -> Result<Cookie<'static>, SessionError> {
+        let session_id = generate_session_id();
+        let mut state = SessionState::new();
+        state.insert(USER_ID_KEY.into(), user_id.to_string());
+        self.store.save(&session_id, &state, SESSION_TTL).await?;
+        Ok(session_cookie(session_id))
+    }
+
+    /// Revoke the current session and return a cookie that clears it in the browser.
+    pub async fn log_out(&self, request: &HttpRequest) -> Result<Cookie<'static>, SessionError> {
+        if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+            self.store.delete(cookie.value()).await?;
+        }
+        let mut removal = session_cookie(String::new());
+        removal.make_removal();
+        Ok(removal)
+    }
+}
+
+fn session_cookie(session_id: String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, session_id)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+fn generate_session_id() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect()
+}
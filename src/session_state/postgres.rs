@@ -0,0 +1,67 @@
+use super::{SessionError, SessionState, SessionStore};
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Keeps sessions in the `sessions` table, shared by every replica.
+pub struct PostgresSessionStore {
+    pool: PgPool
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, session_id: &str) -> Result<Option<SessionState>, SessionError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT state
+            FROM sessions
+            WHERE session_id = $1 AND expires_at > now()
+            "#,
+            session_id
+        )
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| SessionError(e.to_string()))?;
+        row.map(|row| serde_json::from_str(&row.state))
+            .transpose()
+            .map_err(|e| SessionError(e.to_string()))
+    }
+
+    async fn save(&self, session_id: &str, state: &SessionState, ttl: Duration) -> Result<(), SessionError> {
+        let state = serde_json::to_string(state).map_err(|e| SessionError(e.to_string()))?;
+        let expires_at = Utc::now()
+            + chrono::Duration::from_std(ttl).map_err(|e| SessionError(e.to_string()))?;
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_id, state, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (session_id) DO UPDATE
+            SET state = EXCLUDED.state, expires_at = EXCLUDED.expires_at
+            "#,
+            session_id,
+            state,
+            expires_at
+        )
+            .execute(&self.pool)
+            .await
+            .map_err(|e| SessionError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), SessionError> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_id = $1"#,
+            session_id
+        )
+            .execute(&self.pool)
+            .await
+            .map_err(|e| SessionError(e.to_string()))?;
+        Ok(())
+    }
+}
@@ -1,37 +1,91 @@
-use crate::routes::{subscribe, health_check, confirm};
+use crate::routes::{
+    home, subscribe, subscribe_from_page, check_your_inbox, subscribe_json, json_error_handler, health_check, openapi_json, ready, confirm, missing_token_handler, publish_newsletter, login_form, login, admin_dashboard,
+    change_password_form, change_password, log_out, unsubscribe_form, unsubscribe, export_metrics
+};
 use actix_web::dev::Server;
-use actix_web::{App,HttpServer, web};
+use actix_web::http::Method;
+use actix_web::{App, HttpServer, Resource, Route, web};
 use std::net::TcpListener;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 use crate::email_client::EmailClient;
-use crate::configuration::{Settings, DatabaseSettings};
+use crate::email_templates::EmailTemplates;
+use crate::configuration::{Settings, DatabaseSettings, RateLimitStoreKind, SessionStoreKind};
 use sqlx::postgres::PgPoolOptions;
 use actix_web::web::Data;
+use crate::issue_delivery_worker::IssueDeliveryWorker;
+use crate::janitor::Janitor;
+use crate::in_flight::{InFlightRequests, TrackInFlight};
+use crate::metrics::{Metrics, RecordMetrics};
+use crate::authentication::RequireLogin;
+use crate::session_state::{SessionManager, SessionStore, PostgresSessionStore, InMemorySessionStore};
+use crate::bot_protection::BotProtection;
+use crate::rate_limit::{
+    InMemoryRateLimitStore, PostgresRateLimitStore, RateLimit, RateLimitStore, RateLimiter
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinError;
+use secrecy::Secret;
 
 pub struct Application {
     port: u16,
-    server: Server
+    server: Server,
+    worker: IssueDeliveryWorker,
+    janitor: Janitor,
+    db_pool: PgPool,
+    in_flight: InFlightRequests,
+    shutdown_grace_period: Duration
 }
 #[derive(Debug)]
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct HmacSecret(pub Secret<String>);
+
+/// Optional dependencies checked by `/ready`, on top of the database.
+pub struct ReadinessChecks {
+    pub email_provider: bool
+}
+
+/// How long a confirmation link stays valid.
+pub struct SubscriptionTokenTtl(pub std::time::Duration);
+
+/// What Prometheus must send as a bearer token to scrape `/metrics`.
+/// `/metrics` is not served without one.
+pub struct MetricsToken(pub Option<Secret<String>>);
+
 impl Application{
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error>{
         let connection_pool = get_connection_pool(&configuration.database)
             .await
             .expect("Failed to connect to Postgres");
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address");
-
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token
-        );
+        let metrics = Metrics::new().expect("Failed to register the metrics");
+        let email_client = configuration.email_client
+            .clone()
+            .client()
+            .with_metrics(metrics.emails_sent.clone());
+        let templates = EmailTemplates::load(&configuration.application.templates_directory)
+            .expect("Failed to load the email templates");
+        let worker = IssueDeliveryWorker {
+            pool: connection_pool.clone(),
+            email_client: configuration.email_client
+                .clone()
+                .client()
+                .with_metrics(metrics.emails_sent.clone()),
+            templates: templates.clone(),
+            retry_policy: configuration.email_client.retry_policy(),
+            base_url: configuration.application.base_url.clone(),
+            hmac_secret: configuration.application.hmac_secret.clone()
+        };
+        let janitor = Janitor {
+            pool: connection_pool.clone(),
+            unconfirmed_subscriber_retention: configuration.application.unconfirmed_subscriber_retention(),
+            idempotency_key_retention: configuration.application.idempotency_key_retention(),
+            interval: std::time::Duration::from_secs(60 * 60)
+        };
 
         let address = format!(
             "{}:{}",
@@ -41,17 +95,125 @@ impl Application{
 
         let listener = TcpListener::bind(address)?;
         let port =listener.local_addr().unwrap().port();
-        let server = run(listener, connection_pool, email_client, configuration.application.base_url)?;
+        let session_store: Arc<dyn SessionStore> = match configuration.application.session_store {
+            SessionStoreKind::Postgres => Arc::new(PostgresSessionStore::new(connection_pool.clone())),
+            SessionStoreKind::Memory => Arc::new(InMemorySessionStore::default())
+        };
+        let rate_limit_store: Arc<dyn RateLimitStore> = match configuration.rate_limit.store {
+            RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore::new(connection_pool.clone())),
+            RateLimitStoreKind::Memory => Arc::new(InMemoryRateLimitStore::default())
+        };
+        let rate_limiter = RateLimiter::new(rate_limit_store, &configuration.rate_limit);
+        let bot_protection = BotProtection::new(
+            &configuration.bot_protection,
+            configuration.application.hmac_secret.clone()
+        );
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
+        let shutdown_grace_period = configuration.application.shutdown_grace_period();
+        let readiness_checks = ReadinessChecks {
+            email_provider: configuration.email_client.readiness_check
+        };
+        let in_flight = InFlightRequests::default();
+        let server = run(
+            listener,
+            connection_pool.clone(),
+            email_client,
+            templates,
+            configuration.application.base_url,
+            session_store,
+            configuration.application.hmac_secret,
+            subscription_token_ttl,
+            readiness_checks,
+            rate_limiter,
+            bot_protection,
+            shutdown_grace_period,
+            in_flight.clone(),
+            metrics,
+            configuration.telemetry.metrics_token
+        )?;
 
-        Ok(Self{ port, server})
+        Ok(Self{ port, server, worker, janitor, db_pool: connection_pool, in_flight, shutdown_grace_period })
     }
 
     pub fn port(&self) -> u16{
         self.port
     }
 
+    /// Run until SIGTERM or SIGINT, then shut down gracefully.
     pub async fn run_until_stopped(self) ->Result<(), std::io::Error>{
-        self.server.await
+        self.run_until(shutdown_signal()).await
+    }
+
+    /// Run the HTTP server, the newsletter delivery worker and the janitor
+    /// side by side until `shutdown` resolves (or the server stops), then:
+    ///
+    /// 1. stop accepting connections and let in-flight requests complete;
+    /// 2. let the background workers finish their current item;
+    /// 3. close the connection pool.
+    ///
+    /// Steps 1 and 2 are each cut off after the shutdown grace period.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) ->Result<(), std::io::Error>{
+        let Application { server, worker, janitor, db_pool, in_flight, shutdown_grace_period, .. } = self;
+        let (stop_background_workers, stopped) = watch::channel(false);
+        let worker = tokio::spawn(worker.run_until_stopped(stopped.clone()));
+        let janitor = tokio::spawn(janitor.run_until_stopped(stopped));
+
+        let server_handle = server.clone();
+        let outcome = tokio::select! {
+            outcome = server => outcome,
+            _ = shutdown => {
+                tracing::info!("Shutting down: waiting for in-flight requests");
+                server_handle.pause().await;
+                // actix's own graceful stop drops in-flight requests as soon as its
+                // accept loop exits: wait for them to complete before stopping.
+                let drained = tokio::time::timeout(shutdown_grace_period, in_flight.drained()).await;
+                if drained.is_err() {
+                    tracing::warn!("In-flight requests did not complete within the grace period");
+                }
+                server_handle.stop(true).await;
+                Ok(())
+            }
+        };
+
+        tracing::info!("Shutting down: waiting for the background workers");
+        let _ = stop_background_workers.send(true);
+        let background_workers = async {
+            report_exit("Newsletter delivery worker", worker.await);
+            report_exit("Janitor", janitor.await);
+        };
+        if tokio::time::timeout(shutdown_grace_period, background_workers).await.is_err() {
+            tracing::warn!("Background workers did not stop within the grace period");
+        }
+
+        db_pool.close().await;
+        tracing::info!("Shutdown complete");
+        outcome
+    }
+}
+
+/// Resolves on SIGTERM (sent by the platform on deploys) or SIGINT (Ctrl+C).
+pub async fn shutdown_signal() {
+    let interrupt = tokio::signal::ctrl_c();
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), std::io::Error>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => tracing::info!("{} has stopped", task_name),
+        Ok(Err(e)) => tracing::error!(error.message = %e, "{} failed", task_name),
+        Err(e) => tracing::error!(error.message = %e, "{} task failed to complete", task_name)
     }
 }
 
@@ -63,25 +225,147 @@ pub async fn  get_connection_pool(configuration: &DatabaseSettings) ->Result<PgP
         .await
 }
 
+/// A route meant for third parties, described in `ApiDoc`.
+pub struct PublicRoute {
+    pub method: Method,
+    pub path: &'static str,
+    /// Registers the resource for `path`, given the route matching `method`.
+    register: fn(&mut web::ServiceConfig, Resource, Route)
+}
+
+/// Every route registered by `public_api`, one method per path:
+/// `tests/api/openapi.rs` fails when `ApiDoc` documents anything else.
+///
+/// The other routes of `run` are not part of the API: the HTML pages and
+/// forms (`/`, `/subscribe`, `/subscriptions/unsubscribe`, `/login`, ...)
+/// are for browsers and carry CSRF tokens, and the rest are for the admin
+/// or the platform running the application.
+pub static PUBLIC_ROUTES: &[PublicRoute] = &[
+    PublicRoute {
+        method: Method::GET,
+        path: "/health_check",
+        register: |cfg, resource, route| { cfg.service(resource.route(route.to(health_check))); }
+    },
+    PublicRoute {
+        method: Method::GET,
+        path: "/ready",
+        register: |cfg, resource, route| { cfg.service(resource.route(route.to(ready))); }
+    },
+    // Every subscription sends an email: those endpoints are rate limited
+    PublicRoute {
+        method: Method::POST,
+        path: "/subscriptions",
+        register: |cfg, resource, route| {
+            cfg.service(resource.wrap(RateLimit).route(route.to(subscribe)));
+        }
+    },
+    PublicRoute {
+        method: Method::POST,
+        path: "/api/v1/subscriptions",
+        register: |cfg, resource, route| {
+            cfg.service(
+                resource
+                    .wrap(RateLimit)
+                    .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                    .route(route.to(subscribe_json))
+            );
+        }
+    },
+    PublicRoute {
+        method: Method::GET,
+        path: "/subscriptions/confirm",
+        register: |cfg, resource, route| {
+            cfg.service(
+                resource
+                    .app_data(web::QueryConfig::default().error_handler(missing_token_handler))
+                    .route(route.to(confirm))
+            );
+        }
+    }
+];
+
+pub fn public_api(cfg: &mut web::ServiceConfig) {
+    for route in PUBLIC_ROUTES {
+        (route.register)(cfg, web::resource(route.path), web::method(route.method.clone()));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(listener: TcpListener,
            db_pool: PgPool,
            email_client: EmailClient,
-            base_url: String) -> Result<Server, std::io::Error> {
+           templates: EmailTemplates,
+           base_url: String,
+           session_store: Arc<dyn SessionStore>,
+           hmac_secret: Secret<String>,
+           subscription_token_ttl: std::time::Duration,
+           readiness_checks: ReadinessChecks,
+           rate_limiter: RateLimiter,
+           bot_protection: BotProtection,
+           shutdown_grace_period: Duration,
+           in_flight: InFlightRequests,
+           metrics: Metrics,
+           metrics_token: Option<Secret<String>>) -> Result<Server, std::io::Error> {
     let db_pool = web::Data::new(db_pool);
+    let session_manager = Data::new(SessionManager::new(session_store));
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
+    let subscription_token_ttl = Data::new(SubscriptionTokenTtl(subscription_token_ttl));
+    let readiness_checks = Data::new(readiness_checks);
+    let rate_limiter = Data::new(rate_limiter);
+    let bot_protection = Data::new(bot_protection);
+    let metrics_data = Data::new(metrics.clone());
+    let metrics_token = Data::new(MetricsToken(metrics_token));
+    let in_flight_data = Data::new(in_flight.clone());
     let email_client= web::Data::new(email_client);
+    let templates = Data::new(templates);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
 
     let server = HttpServer::new( move || {
         App::new()
+            .wrap(RecordMetrics(metrics.clone()))
+            .wrap(TrackInFlight(in_flight.clone()))
             .wrap(TracingLogger::default())
-            .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .configure(public_api)
+            .route("/", web::get().to(home))
+            .service(
+                web::resource("/subscribe")
+                    .wrap(RateLimit)
+                    .route(web::post().to(subscribe_from_page))
+            )
+            .route("/subscriptions/check-your-inbox", web::get().to(check_your_inbox))
+            .route("/api-docs/openapi.json", web::get().to(openapi_json))
+            .route("/metrics", web::get().to(export_metrics))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/admin")
+                    .wrap(RequireLogin)
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password))
+                    .route("/logout", web::post().to(log_out))
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(templates.clone())
             .app_data(base_url.clone())
+            .app_data(session_manager.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(readiness_checks.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(metrics_data.clone())
+            .app_data(metrics_token.clone())
+            .app_data(in_flight_data.clone())
     })
         .listen(listener)?
+        // Signals are handled by `Application::run_until_stopped`
+        .disable_signals()
+        .shutdown_timeout(shutdown_grace_period.as_secs())
         .run();
     Ok(server)
 }
\ No newline at end of file
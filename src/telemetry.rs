@@ -1,3 +1,9 @@
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::{self, Tracer};
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
 use tracing::Subscriber;
 use tracing::subscriber::set_global_default;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -14,10 +20,12 @@ use tracing_subscriber::layer::SubscriberExt;
 ///
 /// We need Send + Sync to make is possible to pass into `init_subscriber`
 ///
+/// Spans are also exported through OpenTelemetry when an `otlp_tracer` is given.
 pub fn get_subscriber(
     name: String,
     env_filter: String,
-    sink: impl MakeWriter + Sync + Send + 'static
+    sink: impl MakeWriter + Sync + Send + 'static,
+    otlp_tracer: Option<Tracer>
 ) -> impl Subscriber + Send + Sync {
     //If RUST_LOG is not passed use default info
     let env_filter = EnvFilter::try_from_default_env()
@@ -29,6 +37,24 @@ pub fn get_subscriber(
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otlp_tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Build a tracer shipping spans to an OTLP/HTTP collector, e.g. `http://localhost:4318`.
+///
+/// Spans are batched and exported from a dedicated thread: call
+/// `opentelemetry::global::shutdown_tracer_provider` before exiting to flush them.
+pub fn otlp_tracer(service_name: String, endpoint: &str) -> Result<Tracer, TraceError> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+        )
+        .install_batch(opentelemetry::runtime::TokioCurrentThread)
 }
 
 /// Register a subscriber as global defualt to process span data.
@@ -38,4 +64,57 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     //Redirect all log events to our subscribe
     LogTracer::init().expect("Failed to set Logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
+    //Read and write W3C `traceparent` headers, see `tracing-actix-web` and `EmailClient`
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+/// Run `f` on tokio's blocking thread pool, inside the caller's span.
+///
+/// CPU-heavy work (e.g. password hashing) would otherwise stall the actix executor.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> tokio::task::JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::telemetry::{get_subscriber, otlp_tracer};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    /// Bodies are protobuf-encoded: look for raw bytes rather than a string.
+    struct BodyContains(&'static [u8]);
+
+    impl wiremock::Match for BodyContains {
+        fn matches(&self, request: &Request) -> bool {
+            request.body.windows(self.0.len()).any(|window| window == self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn spans_are_exported_to_the_otlp_collector(){
+        let collector = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/traces"))
+            .and(BodyContains(b"export me"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&collector)
+            .await;
+
+        let tracer = otlp_tracer("test".into(), &collector.uri()).unwrap();
+        let subscriber = get_subscriber("test".into(), "info".into(), std::io::sink, Some(tracer));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("export me").in_scope(|| tracing::info!("Inside the span"));
+        });
+
+        // Flushes the batch: blocks until the collector answered
+        tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider)
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,199 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::admin_cli::{Admin, Command};
+
+/// The subscriber created by `create_unconfirmed_subscriber`.
+const EMAIL: &str = "asharma@sw-at.com";
+
+async fn run_admin(app: &TestApp, command: Command) -> Result<String, anyhow::Error> {
+    run_admin_with_input(app, command, "").await
+}
+
+/// Run `command` with `input` piped to its stdin.
+async fn run_admin_with_input(app: &TestApp, command: Command, input: &str) -> Result<String, anyhow::Error> {
+    let admin = Admin::new(app.configuration.clone(), app.db_pool.clone());
+    let mut out = Vec::new();
+    admin.run(command, &mut input.as_bytes(), &mut out).await?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", EMAIL)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[actix_rt::test]
+async fn migrate_is_a_no_op_on_an_up_to_date_database() {
+    let app = spawn_app().await;
+
+    let output = run_admin(&app, Command::Migrate).await.unwrap();
+
+    assert_eq!(output, "Migrations applied\n");
+}
+
+#[actix_rt::test]
+async fn created_users_can_log_in() {
+    let app = spawn_app().await;
+
+    run_admin_with_input(&app, Command::CreateUser { username: "ursula".into() }, "everything-is-a-mirror\n")
+        .await
+        .unwrap();
+
+    let response = app.post_login(&serde_json::json!({
+        "username": "ursula",
+        "password": "everything-is-a-mirror"
+    })).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_rt::test]
+async fn usernames_must_be_unique() {
+    let app = spawn_app().await;
+
+    let outcome = run_admin_with_input(
+        &app,
+        Command::CreateUser { username: app.test_user.username.clone() },
+        "another-password\n"
+    ).await;
+
+    assert!(outcome.is_err());
+}
+
+#[actix_rt::test]
+async fn users_cannot_be_created_without_a_password() {
+    let app = spawn_app().await;
+
+    let outcome = run_admin(&app, Command::CreateUser { username: "ursula".into() }).await;
+
+    let error = outcome.unwrap_err().to_string();
+    assert!(error.contains("ZERO2PROD_ADMIN_PASSWORD"), "{}", error);
+    let users = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE username = 'ursula'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users.count, 0);
+}
+
+#[actix_rt::test]
+async fn subscribers_can_be_filtered_by_status_and_searched() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+
+    let list = |status: Option<&str>, search: Option<&str>| run_admin(&app, Command::ListSubscribers {
+        status: status.map(String::from),
+        search: search.map(String::from)
+    });
+
+    let output = list(None, None).await.unwrap();
+    assert!(output.contains(&format!("\t{}\tAtul Sharma\tpending_confirmation\t", EMAIL)));
+    assert!(list(Some("pending_confirmation"), None).await.unwrap().contains(EMAIL));
+    assert_eq!(list(Some("confirmed"), None).await.unwrap(), "");
+    assert!(list(None, Some("SHARMA")).await.unwrap().contains(EMAIL));
+    assert!(list(None, Some("sw-at")).await.unwrap().contains(EMAIL));
+    // `_` is not a wildcard
+    assert_eq!(list(None, Some("a_harma")).await.unwrap(), "");
+}
+
+#[actix_rt::test]
+async fn subscribers_can_be_confirmed_and_unsubscribed_by_email() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+
+    run_admin(&app, Command::Confirm { email: EMAIL.into() }).await.unwrap();
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+
+    run_admin(&app, Command::Unsubscribe { email: EMAIL.into() }).await.unwrap();
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[actix_rt::test]
+async fn unsubscribed_subscribers_cannot_be_confirmed() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    run_admin(&app, Command::Unsubscribe { email: EMAIL.into() }).await.unwrap();
+
+    let outcome = run_admin(&app, Command::Confirm { email: EMAIL.into() }).await;
+
+    assert!(outcome.unwrap_err().to_string().contains("not pending_confirmation"));
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[actix_rt::test]
+async fn unknown_emails_are_reported() {
+    let app = spawn_app().await;
+
+    let outcome = run_admin(&app, Command::Confirm { email: "nobody@example.com".into() }).await;
+
+    assert!(outcome.unwrap_err().to_string().contains("nobody@example.com"));
+}
+
+#[actix_rt::test]
+async fn resending_a_confirmation_sends_a_link_that_works() {
+    let app = spawn_app().await;
+    let first_links = app.create_unconfirmed_subscriber().await;
+
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    run_admin(&app, Command::ResendConfirmation { email: EMAIL.into() }).await.unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let new_links = app.get_confirmation_links(&email_request);
+    assert_ne!(new_links.html, first_links.html);
+    // The previous link was revoked
+    let response = reqwest::get(first_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(new_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[actix_rt::test]
+async fn confirmed_subscribers_do_not_get_a_new_confirmation() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+
+    Mock::given(path("/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let outcome = run_admin(&app, Command::ResendConfirmation { email: EMAIL.into() }).await;
+
+    assert!(outcome.is_err());
+}
+
+#[actix_rt::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+
+    let output = run_admin(&app, Command::Export { status: None, output: None }).await.unwrap();
+
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "id,email,name,status,subscribed_at");
+    assert!(lines[1].contains(&format!(",{},Atul Sharma,pending_confirmation,", EMAIL)));
+}
+
+#[actix_rt::test]
+async fn exports_can_be_written_to_a_file() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let path = std::env::temp_dir().join(format!("{}.csv", uuid::Uuid::new_v4()));
+
+    run_admin(&app, Command::Export { status: Some("confirmed".into()), output: Some(path.clone()) })
+        .await
+        .unwrap();
+
+    let exported = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(exported.contains(EMAIL));
+}
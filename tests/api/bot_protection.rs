@@ -0,0 +1,211 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use chrono::{Duration, Utc};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{CaptchaProvider, CaptchaSettings, Settings};
+use zero2prod::domain::FormToken;
+
+const SUBSCRIBER: &str = "name=Atul%20Sharma&email=asharma%40sw-at.com";
+/// The CSRF cookie of the browser the test forms are served to.
+const CSRF_COOKIE: &str = "browserbrowserbrowserbrowser1234";
+
+async fn spawn_protected_app(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.enabled = true;
+        c.bot_protection.min_submit_seconds = 3;
+        configure(c);
+    }).await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+/// A token for a form served `seconds` ago to the browser holding `CSRF_COOKIE`.
+fn form_token(app: &TestApp, seconds: i64) -> String {
+    FormToken::sign(
+        Utc::now() - Duration::seconds(seconds),
+        CSRF_COOKIE,
+        app.configuration.application.hmac_secret.expose_secret()
+    )
+        .as_ref()
+        .to_owned()
+}
+
+/// Post the form from the browser holding `csrf_cookie`.
+async fn post_subscription_from(app: &TestApp, body: String, csrf_cookie: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Cookie", format!("_csrf={}", csrf_cookie))
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn post_subscription(app: &TestApp, body: String) -> reqwest::Response {
+    post_subscription_from(app, body, CSRF_COOKIE).await
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[actix_rt::test]
+async fn submissions_paced_like_a_human_go_through() {
+    let app = spawn_protected_app(|_| {}).await;
+
+    let body = format!("{}&website=&form_token={}", SUBSCRIBER, form_token(&app, 10));
+    let response = post_subscription(&app, body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 1);
+}
+
+#[actix_rt::test]
+async fn a_filled_in_honeypot_is_silently_ignored() {
+    let app = spawn_protected_app(|_| {}).await;
+
+    let body = format!(
+        "{}&website=https%3A%2F%2Fspam.example&form_token={}",
+        SUBSCRIBER,
+        form_token(&app, 10)
+    );
+    let response = post_subscription(&app, body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn submissions_without_a_valid_fresh_form_token_are_rejected() {
+    let app = spawn_protected_app(|_| {}).await;
+    let test_cases = vec![
+        (SUBSCRIBER.to_string(), "no token"),
+        (format!("{}&form_token=1630000000.forged", SUBSCRIBER), "a forged token"),
+        (format!("{}&form_token={}", SUBSCRIBER, form_token(&app, 0)), "a form submitted too quickly"),
+        (format!("{}&form_token={}", SUBSCRIBER, form_token(&app, 2 * 24 * 60 * 60)), "an expired form"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = post_subscription(&app, body).await;
+        assert_eq!(response.status().as_u16(), 400, "Accepted {}", description);
+        assert_eq!(
+            response.headers()["Content-Type"].to_str().unwrap(),
+            "application/problem+json"
+        );
+    }
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[actix_rt::test]
+async fn the_captcha_is_verified_with_the_provider_when_configured() {
+    let captcha_server = MockServer::start().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=captcha-secret"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": true})))
+        .mount(&captcha_server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": false})))
+        .mount(&captcha_server)
+        .await;
+    let verify_url = format!("{}/siteverify", captcha_server.uri());
+    let app = spawn_protected_app(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            provider: CaptchaProvider::Turnstile,
+            site_key: "site-key".into(),
+            secret_key: Secret::new("captcha-secret".into()),
+            verify_url: Some(verify_url),
+            timeout_milliseconds: 1000
+        });
+    }).await;
+    let token = form_token(&app, 10);
+
+    let body = format!("{}&form_token={}&cf-turnstile-response=guessed", SUBSCRIBER, token);
+    let response = post_subscription(&app, body).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = post_subscription(&app, format!("{}&form_token={}", SUBSCRIBER, token)).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = format!("{}&form_token={}&cf-turnstile-response=solved", SUBSCRIBER, token);
+    let response = post_subscription(&app, body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 1);
+}
+
+#[actix_rt::test]
+async fn form_tokens_only_work_for_the_browser_they_were_served_to() {
+    let app = spawn_protected_app(|_| {}).await;
+    let body = format!("{}&website=&form_token={}", SUBSCRIBER, form_token(&app, 10));
+
+    let response = post_subscription_from(&app, body.clone(), &"a".repeat(32)).await;
+    assert_eq!(response.status().as_u16(), 400);
+    // Nor can the token be replayed without any cookie
+    let response = app.post_subscription(body).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[actix_rt::test]
+async fn the_json_api_only_needs_a_captcha_when_one_is_configured() {
+    let app = spawn_protected_app(|_| {}).await;
+
+    let response = app
+        .post_subscription_json(&serde_json::json!({"name": "Atul Sharma", "email": "asharma@sw-at.com"}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn the_json_api_checks_the_captcha_when_configured() {
+    let captcha_server = MockServer::start().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": true})))
+        .mount(&captcha_server)
+        .await;
+    let verify_url = format!("{}/siteverify", captcha_server.uri());
+    let app = spawn_protected_app(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            provider: CaptchaProvider::HCaptcha,
+            site_key: "site-key".into(),
+            secret_key: Secret::new("captcha-secret".into()),
+            verify_url: Some(verify_url),
+            timeout_milliseconds: 1000
+        });
+    }).await;
+
+    let response = app
+        .post_subscription_json(&serde_json::json!({"name": "Atul Sharma", "email": "asharma@sw-at.com"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "application/problem+json"
+    );
+    assert_eq!(subscriber_count(&app).await, 0);
+
+    let response = app
+        .post_subscription_json(&serde_json::json!({
+            "name": "Atul Sharma",
+            "email": "asharma@sw-at.com",
+            "h-captcha-response": "solved"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 1);
+}
@@ -1,4 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, TestApp};
+use sqlx::{Connection, Executor, PgConnection};
+use wiremock::matchers::method;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
 
 #[actix_rt::test]
 async fn health_check_works(){
@@ -18,4 +22,70 @@ async fn health_check_works(){
     // Assert
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
-}
\ No newline at end of file
+}
+async fn get_ready(app: &TestApp) -> (u16, serde_json::Value) {
+    let response = reqwest::get(&format!("{}/ready", app.address))
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[actix_rt::test]
+async fn ready_reports_every_component_up(){
+    let app = spawn_app().await;
+
+    let (status, body) = get_ready(&app).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "up");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["email_provider"]["status"], "up");
+    assert!(body["checks"]["database"]["latency_ms"].is_u64());
+}
+
+#[actix_rt::test]
+async fn ready_returns_503_when_the_email_provider_fails(){
+    let app = spawn_app().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+
+    let (status, body) = get_ready(&app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+}
+
+#[actix_rt::test]
+async fn ready_returns_503_when_the_database_is_unreachable(){
+    let app = spawn_app().await;
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // Refuse new connections and kill the open ones, the application's included
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let mut connection = PgConnection::connect_with(&configuration.database.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"ALTER DATABASE "{}" ALLOW_CONNECTIONS false"#, database_name).as_str())
+        .await
+        .unwrap();
+    sqlx::query("SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = $1")
+        .bind(&database_name)
+        .execute(&mut connection)
+        .await
+        .unwrap();
+
+    let (status, body) = get_ready(&app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["checks"]["database"]["status"], "down");
+    assert_eq!(body["checks"]["email_provider"]["status"], "up");
+}
@@ -1,37 +1,130 @@
 
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, SessionStoreKind, Settings};
 use zero2prod::startup::{Application, get_connection_pool};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, IssueDeliveryWorker};
+use zero2prod::email_templates::EmailTemplates;
+use wiremock::{Mock, ResponseTemplate};
+use wiremock::matchers::{method, path};
 use sqlx::{PgPool, PgConnection, Connection, Executor};
 use uuid::Uuid;
 use once_cell::sync::Lazy;
+use std::io::Write;
+use std::sync::Mutex;
 use wiremock::MockServer;
+use reqwest::Url;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use secrecy::Secret;
 
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subsciber_name= "test".to_string();
 
-    if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subsciber_name, default_filter_level, std::io::stdout);
-        init_subscriber(subscriber);
+    let echo = std::env::var("TEST_LOG").is_ok();
+    let subscriber = get_subscriber(subsciber_name, default_filter_level, move || LogWriter { echo }, None);
+    init_subscriber(subscriber);
+});
+
+/// What the test apps expect Prometheus to scrape `/metrics` with.
+pub const METRICS_TOKEN: &str = "metrics-token";
+
+/// Every log line of every test, to check what must never be logged.
+static LOGS: Lazy<Mutex<Vec<u8>>> = Lazy::new(Mutex::default);
+
+/// Keeps log lines in `LOGS`, and prints them too with `TEST_LOG` set.
+struct LogWriter {
+    echo: bool
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        LOGS.lock().unwrap().extend_from_slice(buf);
+        if self.echo {
+            std::io::stdout().write_all(buf)?;
+        }
+        Ok(buf.len())
     }
-    else{
-        let subscriber = get_subscriber(subsciber_name, default_filter_level, std::io::sink);
-        init_subscriber(subscriber);
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
+}
 
-});
+/// Everything logged so far, by every test running in this process.
+pub fn captured_logs() -> String {
+    String::from_utf8_lossy(&LOGS.lock().unwrap()).into_owned()
+}
+
+
+/// Confirmation links embedded in the request to the email API.
+pub struct ConfirmationLinks {
+    pub html: Url,
+    pub plain_text: Url
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String
+}
 
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string()
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(self.password.clone()).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash
+        )
+            .execute(pool)
+            .await
+            .expect("Failed to store test user.");
+    }
+}
 
 pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
-    pub port: u16
+    /// What the application was built with, e.g. to run admin commands against it.
+    pub configuration: Settings,
+    pub port: u16,
+    /// Lets tests drain the delivery queue on demand.
+    pub delivery_worker: IssueDeliveryWorker,
+    pub test_user: TestUser,
+    /// Keeps cookies and does not follow redirects, like a browser tab we can inspect.
+    pub api_client: reqwest::Client,
+    /// Plays the part of SIGTERM, see `shut_down`.
+    shutdown: Option<oneshot::Sender<()>>,
+    application: Option<JoinHandle<Result<(), std::io::Error>>>
 }
 
 impl TestApp {
+    /// Ask the application to shut down gracefully, as SIGTERM would, and wait until it has.
+    pub async fn shut_down(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(application) = self.application.take() {
+            application
+                .await
+                .expect("The application panicked")
+                .expect("The application failed to shut down");
+        }
+    }
+
     pub async fn post_subscription(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
             .post(format!("{}/subscriptions", &self.address))
@@ -41,10 +134,240 @@ impl TestApp {
             .await
             .expect("Failed to execute request")
     }
+
+    pub async fn post_subscription_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscription_with_idempotency_key(&self, body: String, idempotency_key: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type","application/x-www-form-urlencoded")
+            .header("Idempotency-Key", idempotency_key)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribe_page_html(&self) -> String {
+        self.api_client
+            .get(&self.address)
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Post the form served at `/`, with the cookies the page set.
+    pub async fn post_subscribe_page<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscribe", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Deliver every queued newsletter email before returning.
+    ///
+    /// The application's own worker may be holding a row while we drain the
+    /// queue, so we wait for no task to be due rather than trusting
+    /// `EmptyQueue` alone.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self.delivery_worker.try_execute_task().await.unwrap()
+            {
+                let pending = sqlx::query!(
+                    r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE execute_after <= now()"#
+                )
+                    .fetch_one(&self.db_pool)
+                    .await
+                    .unwrap()
+                    .count;
+                if pending == 0 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        }
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(&self, body: serde_json::Value, idempotency_key: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn login_as_test_user(&self) {
+        self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password
+        }))
+            .await;
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Use the public API of the application under test to create
+    /// an unconfirmed subscriber.
+    pub async fn create_unconfirmed_subscriber(&self) -> ConfirmationLinks {
+        let body = "name=Atul%20Sharma&email=asharma%40sw-at.com";
+
+        let _mock_guard = Mock::given(path("/mail/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscription(body.into())
+            .await
+            .error_for_status()
+            .unwrap();
+
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_links(email_request)
+    }
+
+    pub async fn create_confirmed_subscriber(&self) {
+        let confirmation_link = self.create_unconfirmed_subscriber().await;
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    /// Extract the confirmation links from the request intercepted by the email server.
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                // Every email also carries an unsubscribe link in its footer
+                .filter(|l| l.as_str().contains("/subscriptions/confirm"))
+                .collect();
+            assert_eq!(links.len(), 1);
+            let raw_link = links[0].as_str().to_owned();
+            let mut confirmation_link = Url::parse(&raw_link).unwrap();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+
+        let plain_text = get_link(body["content"][0]["value"].as_str().unwrap());
+        let html = get_link(body["content"][1]["value"].as_str().unwrap());
+        ConfirmationLinks { html, plain_text }
+    }
 }
 
 // only dependency to our application
 pub async fn  spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, with a chance to adjust the configuration first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed
     Lazy::force(&TRACING);
 
@@ -54,7 +377,17 @@ pub async fn  spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Failed to read configuration file");
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.application.session_store = SessionStoreKind::Memory;
         c.email_client.base_url = email_server.uri();
+        // Retry failed deliveries straight away
+        c.email_client.min_backoff_milliseconds = 0;
+        c.email_client.max_backoff_milliseconds = 0;
+        c.email_client.readiness_check = true;
+        // Every test client comes from 127.0.0.1: only rate limit tests get low limits
+        c.rate_limit.per_ip.capacity = 10_000;
+        c.rate_limit.per_email.capacity = 10_000;
+        c.telemetry.metrics_token = Some(Secret::new(METRICS_TOKEN.into()));
+        configure(&mut c);
         c
     };
 
@@ -67,16 +400,41 @@ pub async fn  spawn_app() -> TestApp {
     let address = format!("http://127.0.0.1:{}", application.port());
 
     // launch server as background task
-    let _ = tokio::spawn(application.run_until_stopped());
+    let (shutdown, shutdown_signal) = oneshot::channel();
+    let application = tokio::spawn(application.run_until(async {
+        let _ = shutdown_signal.await;
+    }));
 
-    TestApp {
+    let test_app = TestApp {
         address,
         port: application_port,
         db_pool: get_connection_pool(&configuration.database)
             .await
             .expect("Faled to connect to database"),
-        email_server
-    }
+        email_server,
+        delivery_worker: IssueDeliveryWorker {
+            pool: get_connection_pool(&configuration.database)
+                .await
+                .expect("Faled to connect to database"),
+            email_client: configuration.email_client.clone().client(),
+            retry_policy: configuration.email_client.retry_policy(),
+            base_url: configuration.application.base_url.clone(),
+            hmac_secret: configuration.application.hmac_secret.clone(),
+            templates: EmailTemplates::load(&configuration.application.templates_directory)
+                .expect("Failed to load the email templates")
+        },
+        test_user: TestUser::generate(),
+        api_client: reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap(),
+        configuration,
+        shutdown: Some(shutdown),
+        application: Some(application)
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
 }
 pub async fn configure_database(config: &DatabaseSettings) ->PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
@@ -95,3 +453,7 @@ pub async fn configure_database(config: &DatabaseSettings) ->PgPool {
     connection_pool
 }
 
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
@@ -0,0 +1,178 @@
+use crate::helpers::{spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use zero2prod::janitor::{Janitor, PurgeReport};
+
+fn janitor(app: &TestApp) -> Janitor {
+    Janitor {
+        pool: app.db_pool.clone(),
+        unconfirmed_subscriber_retention: Duration::from_secs(30 * 24 * 60 * 60),
+        idempotency_key_retention: Duration::from_secs(48 * 60 * 60),
+        interval: Duration::from_secs(60)
+    }
+}
+
+/// Store a subscriber who subscribed `age_in_days` ago, with a token
+/// expiring `token_expires_in_days` from now.
+async fn insert_subscriber(app: &TestApp, status: &str, age_in_days: i32, token_expires_in_days: i32) {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Atul Sharma', now() - make_interval(days => $3), $4)
+        "#,
+        subscriber_id,
+        format!("{}@sw-at.com", subscriber_id),
+        age_in_days,
+        status
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, expires_at)
+        VALUES ($1, $2, now() + make_interval(days => $3))
+        "#,
+        subscriber_id.to_string(),
+        subscriber_id,
+        token_expires_in_days
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[actix_rt::test]
+async fn expired_tokens_are_kept_while_their_subscriber_is_pending() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "pending_confirmation", 3, -1).await;
+    insert_subscriber(&app, "pending_confirmation", 1, 1).await;
+
+    let report = janitor(&app).purge().await.unwrap();
+
+    assert_eq!(report, PurgeReport::default());
+    assert_eq!(count_subscribers(&app).await, 2);
+}
+
+#[actix_rt::test]
+async fn a_retention_too_long_to_compute_purges_nothing() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "pending_confirmation", 31, -29).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (idempotency_key, request_path, created_at)
+        VALUES ('old', '/subscriptions', now() - interval '1 year')
+        "#
+    )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let janitor = Janitor {
+        unconfirmed_subscriber_retention: Duration::from_secs(u64::MAX),
+        idempotency_key_retention: Duration::from_secs(u64::MAX),
+        ..janitor(&app)
+    };
+
+    let report = janitor.purge().await.unwrap();
+
+    assert_eq!(report.unconfirmed_subscribers, 0);
+    assert_eq!(report.expired_tokens, 0);
+    assert_eq!(report.expired_idempotency_keys, 0);
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[actix_rt::test]
+async fn expired_links_still_say_so_after_a_purge() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 day'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    janitor(&app).purge().await.unwrap();
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[actix_rt::test]
+async fn only_stale_pending_subscribers_are_purged() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "pending_confirmation", 31, -29).await;
+    insert_subscriber(&app, "pending_confirmation", 29, -27).await;
+    insert_subscriber(&app, "confirmed", 31, -29).await;
+    insert_subscriber(&app, "unsubscribed", 31, -29).await;
+
+    let report = janitor(&app).purge().await.unwrap();
+
+    assert_eq!(report.unconfirmed_subscribers, 1);
+    // The stale subscriber's, and the expired ones of who is no longer pending
+    assert_eq!(report.expired_tokens, 3);
+    assert_eq!(count_subscribers(&app).await, 3);
+}
+
+#[actix_rt::test]
+async fn only_old_idempotency_keys_are_purged() {
+    let app = spawn_app().await;
+    for (key, age_in_hours) in [("old", 49), ("recent", 47)] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency (idempotency_key, request_path, created_at)
+            VALUES ($1, '/subscriptions', now() - make_interval(hours => $2))
+            "#,
+            key,
+            age_in_hours
+        )
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+
+    let report = janitor(&app).purge().await.unwrap();
+
+    assert_eq!(report.expired_idempotency_keys, 1);
+    let remaining = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].idempotency_key, "recent");
+}
+
+#[actix_rt::test]
+async fn only_expired_sessions_are_purged() {
+    let app = spawn_app().await;
+    for (session_id, expires_in_hours) in [("expired", -1), ("active", 1)] {
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_id, state, expires_at)
+            VALUES ($1, '{}', now() + make_interval(hours => $2))
+            "#,
+            session_id,
+            expires_in_hours
+        )
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+
+    let report = janitor(&app).purge().await.unwrap();
+
+    assert_eq!(report.expired_sessions, 1);
+    let remaining = sqlx::query!("SELECT session_id FROM sessions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].session_id, "active");
+}
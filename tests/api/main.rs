@@ -1,4 +1,19 @@
+mod admin_cli;
+mod admin_dashboard;
+mod bot_protection;
+mod change_password;
 mod helpers;
+mod janitor;
 mod health_check;
+mod login;
+mod metrics;
+mod newsletters;
+mod openapi;
+mod rate_limit;
+mod session_store;
+mod shutdown;
+mod subscribe_page;
 mod subscriptions;
-mod subscription_confirm;
\ No newline at end of file
+mod subscriptions_api;
+mod subscription_confirm;
+mod unsubscribe;
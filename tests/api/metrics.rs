@@ -0,0 +1,79 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp, METRICS_TOKEN};
+
+async fn get_metrics(app: &TestApp) -> String {
+    let response = reqwest::Client::new()
+        .get(format!("{}/metrics", app.address))
+        .bearer_auth(METRICS_TOKEN)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+#[actix_rt::test]
+async fn requests_are_counted_by_route_pattern_and_status() {
+    let app = spawn_app().await;
+    reqwest::get(&format!("{}/health_check", app.address)).await.unwrap();
+    reqwest::get(&format!("{}/subscriptions/confirm?subscription_token=abc", app.address))
+        .await
+        .unwrap();
+    reqwest::get(&format!("{}/does-not-exist/42", app.address)).await.unwrap();
+
+    let metrics = get_metrics(&app).await;
+
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="/health_check",status="200"} 1"#));
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="/subscriptions/confirm",status="401"} 1"#));
+    assert!(metrics.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#));
+    assert!(metrics.contains(r#"http_request_duration_seconds_count{method="GET",route="/health_check",status="200"} 1"#));
+}
+
+#[actix_rt::test]
+async fn subscribers_and_emails_are_exported() {
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+
+    let metrics = get_metrics(&app).await;
+
+    assert!(metrics.contains(r#"subscribers{status="pending_confirmation"} 1"#));
+    assert!(metrics.contains(r#"emails_sent_total{outcome="sent",transport="sendgrid"} 1"#));
+    assert!(metrics.contains(r#"db_pool_connections{state="in_use"}"#));
+}
+
+#[actix_rt::test]
+async fn requests_in_flight_are_exported() {
+    let app = spawn_app().await;
+
+    let metrics = get_metrics(&app).await;
+
+    // The scrape itself
+    assert!(metrics.contains("http_requests_in_flight 1"));
+}
+
+#[actix_rt::test]
+async fn metrics_are_only_served_with_the_token() {
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/metrics", app.address);
+
+    let anonymous = client.get(&url).send().await.unwrap();
+    let wrong_token = client.get(&url).bearer_auth("not-the-token").send().await.unwrap();
+
+    assert_eq!(anonymous.status().as_u16(), 401);
+    assert_eq!(anonymous.headers()["WWW-Authenticate"], r#"Bearer realm="metrics""#);
+    assert_eq!(wrong_token.status().as_u16(), 401);
+}
+
+#[actix_rt::test]
+async fn metrics_are_not_served_without_a_token_configured() {
+    let app = spawn_app_with(|c| c.telemetry.metrics_token = None).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/metrics", app.address))
+        .bearer_auth(METRICS_TOKEN)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
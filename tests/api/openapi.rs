@@ -0,0 +1,105 @@
+use crate::helpers::spawn_app;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use utoipa::OpenApi;
+use zero2prod::configuration::get_configuration;
+use zero2prod::rate_limit::{InMemoryRateLimitStore, RateLimiter};
+use zero2prod::routes::ApiDoc;
+use zero2prod::startup::{public_api, PUBLIC_ROUTES};
+
+/// `(method, path)` of every operation in `ApiDoc`.
+fn documented_operations() -> BTreeSet<(String, String)> {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, operations)| {
+            operations
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(move |method| (method.to_uppercase(), path.clone()))
+        })
+        .collect()
+}
+
+async fn get_spec() -> serde_json::Value {
+    let app = spawn_app().await;
+    let response = reqwest::get(format!("{}/api-docs/openapi.json", &app.address))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[actix_rt::test]
+async fn the_openapi_spec_is_served() {
+    let spec = get_spec().await;
+
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    for path in &["/subscriptions", "/subscriptions/confirm", "/health_check"] {
+        assert!(spec["paths"].get(path).is_some(), "{} is not documented", path);
+    }
+    assert_eq!(
+        spec["paths"]["/subscriptions/confirm"]["get"]["parameters"][0]["name"],
+        "subscription_token"
+    );
+}
+
+#[actix_rt::test]
+async fn the_openapi_spec_never_mentions_the_honeypot() {
+    let spec = get_spec().await.to_string().to_lowercase();
+
+    for giveaway in &["website", "honeypot", "form_token"] {
+        assert!(!spec.contains(giveaway), "The spec mentions `{}`", giveaway);
+    }
+}
+
+#[actix_rt::test]
+async fn the_openapi_spec_matches_the_registered_routes() {
+    let public_routes: BTreeSet<(String, String)> = PUBLIC_ROUTES
+        .iter()
+        .map(|route| (route.method.to_string(), route.path.to_string()))
+        .collect();
+    assert!(public_routes.contains(&("POST".to_string(), "/api/v1/subscriptions".to_string())));
+    assert_eq!(
+        public_routes, documented_operations(),
+        "`startup::PUBLIC_ROUTES` (left) and the operations documented in `ApiDoc` (right) differ"
+    );
+
+    let settings = get_configuration().expect("Failed to read configuration.").rate_limit;
+    let rate_limiter = RateLimiter::new(Arc::new(InMemoryRateLimitStore::default()), &settings);
+    let app = test::init_service(
+        App::new()
+            .configure(public_api)
+            .app_data(web::Data::new(rate_limiter))
+    ).await;
+    for route in PUBLIC_ROUTES {
+        // Each route reaches a handler. Without the rest of the application
+        // state most of them fail, but neither with a 404 nor with a 405...
+        let request = test::TestRequest::default()
+            .method(route.method.clone())
+            .uri(route.path)
+            .to_request();
+        let status = test::call_service(&app, request).await.status();
+        assert!(
+            status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+            "{} {} is documented but not routed ({})", route.method, route.path, status
+        );
+        // ...and only for the documented method.
+        for method in &[Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            if *method == route.method {
+                continue;
+            }
+            let request = test::TestRequest::default().method(method.clone()).uri(route.path).to_request();
+            let status = test::call_service(&app, request).await.status();
+            assert!(
+                status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED,
+                "{} {} is routed but not documented ({})", method, route.path, status
+            );
+        }
+    }
+}
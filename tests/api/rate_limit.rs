@@ -0,0 +1,146 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{RateLimitStoreKind, Settings};
+use zero2prod::janitor::Janitor;
+use zero2prod::rate_limit::{Decision, Limit, PostgresRateLimitStore, RateLimitStore};
+
+async fn spawn_rate_limited_app(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let app = spawn_app_with(configure).await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+fn subscription(email: &str) -> String {
+    format!("name=Atul%20Sharma&email={}", email.replace('@', "%40"))
+}
+
+fn assert_is_rate_limited(response: &reqwest::Response) {
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "application/problem+json"
+    );
+    let retry_after: u64 = response.headers()["Retry-After"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0);
+}
+
+#[actix_rt::test]
+async fn clients_are_limited_by_ip() {
+    let app = spawn_rate_limited_app(|c| {
+        c.rate_limit.store = RateLimitStoreKind::Memory;
+        c.rate_limit.per_ip.capacity = 2;
+        c.rate_limit.per_ip.refill_interval_seconds = 3600;
+    }).await;
+
+    for i in 0..2 {
+        let response = app.post_subscription(subscription(&format!("{}@sw-at.com", i))).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_subscription(subscription("2@sw-at.com")).await;
+
+    assert_is_rate_limited(&response);
+    let count = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, 2);
+}
+
+#[actix_rt::test]
+async fn emails_are_limited_whatever_their_case_and_endpoint() {
+    let app = spawn_rate_limited_app(|c| {
+        c.rate_limit.per_email.capacity = 2;
+        c.rate_limit.per_email.refill_interval_seconds = 3600;
+    }).await;
+
+    let response = app.post_subscription(subscription("asharma@sw-at.com")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_subscription_json(&serde_json::json!({"name": "Atul Sharma", "email": "ASharma@sw-at.com"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_subscription(subscription("asharma@SW-AT.com")).await;
+    assert_is_rate_limited(&response);
+    let response = app
+        .post_subscribe_page(&serde_json::json!({"name": "Atul Sharma", "email": "asharma@sw-at.com"}))
+        .await;
+    assert_is_rate_limited(&response);
+    let emails_sent = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(emails_sent, 2);
+
+    // Other subscribers are not affected
+    let response = app.post_subscription(subscription("ursula@sw-at.com")).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn the_forwarded_address_is_trusted_only_when_configured() {
+    let post_from = |app: &TestApp, ip: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("203.0.113.1, {}", ip))
+            .body(subscription(&format!("{}@sw-at.com", uuid::Uuid::new_v4())))
+            .send()
+    };
+    let limit_to_one_per_ip = |c: &mut Settings| {
+        c.rate_limit.per_ip.capacity = 1;
+        c.rate_limit.per_ip.refill_interval_seconds = 3600;
+    };
+
+    let app = spawn_rate_limited_app(|c| {
+        limit_to_one_per_ip(c);
+        c.rate_limit.use_forwarded_headers = true;
+    }).await;
+    assert_eq!(post_from(&app, "198.51.100.1").await.unwrap().status().as_u16(), 200);
+    assert_eq!(post_from(&app, "198.51.100.2").await.unwrap().status().as_u16(), 200);
+    assert_is_rate_limited(&post_from(&app, "198.51.100.1").await.unwrap());
+
+    let app = spawn_rate_limited_app(limit_to_one_per_ip).await;
+    assert_eq!(post_from(&app, "198.51.100.1").await.unwrap().status().as_u16(), 200);
+    assert_is_rate_limited(&post_from(&app, "198.51.100.2").await.unwrap());
+}
+
+#[actix_rt::test]
+async fn other_routes_are_not_rate_limited() {
+    let app = spawn_rate_limited_app(|c| {
+        c.rate_limit.per_ip.capacity = 1;
+        c.rate_limit.per_ip.refill_interval_seconds = 3600;
+    }).await;
+
+    for _ in 0..3 {
+        let response = reqwest::get(format!("{}/health_check", &app.address)).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[actix_rt::test]
+async fn the_postgres_store_is_shared_and_purged_once_full() {
+    let app = spawn_app_with(|_| {}).await;
+    let limit = Limit { capacity: 1, refill_interval: Duration::from_secs(1) };
+    let replica_a = PostgresRateLimitStore::new(app.db_pool.clone());
+    let replica_b = PostgresRateLimitStore::new(app.db_pool.clone());
+
+    assert_eq!(replica_a.take("ip:192.0.2.1", &limit).await.unwrap(), Decision::Allowed);
+    assert!(matches!(
+        replica_b.take("ip:192.0.2.1", &limit).await.unwrap(),
+        Decision::Limited { .. }
+    ));
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let janitor = Janitor {
+        pool: app.db_pool.clone(),
+        unconfirmed_subscriber_retention: Duration::from_secs(60),
+        idempotency_key_retention: Duration::from_secs(60),
+        interval: Duration::from_secs(60)
+    };
+    let report = janitor.purge().await.unwrap();
+    assert_eq!(report.full_rate_limit_buckets, 1);
+}
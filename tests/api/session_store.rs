@@ -0,0 +1,38 @@
+use crate::helpers::spawn_app;
+use std::time::Duration;
+use zero2prod::session_state::{
+    InMemorySessionStore, PostgresSessionStore, SessionState, SessionStore
+};
+
+fn state() -> SessionState {
+    let mut state = SessionState::new();
+    state.insert("user_id".into(), uuid::Uuid::new_v4().to_string());
+    state
+}
+
+/// Every backend must behave the same way.
+async fn assert_store_contract(store: &dyn SessionStore) {
+    let state = state();
+
+    store.save("live", &state, Duration::from_secs(60)).await.unwrap();
+    assert_eq!(store.load("live").await.unwrap(), Some(state.clone()));
+
+    store.delete("live").await.unwrap();
+    assert_eq!(store.load("live").await.unwrap(), None);
+
+    store.save("expired", &state, Duration::from_secs(0)).await.unwrap();
+    assert_eq!(store.load("expired").await.unwrap(), None);
+
+    assert_eq!(store.load("unknown").await.unwrap(), None);
+}
+
+#[actix_rt::test]
+async fn postgres_session_store_honours_the_contract() {
+    let app = spawn_app().await;
+    assert_store_contract(&PostgresSessionStore::new(app.db_pool.clone())).await;
+}
+
+#[actix_rt::test]
+async fn in_memory_session_store_honours_the_contract() {
+    assert_store_contract(&InMemorySessionStore::default()).await;
+}
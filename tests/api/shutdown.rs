@@ -0,0 +1,51 @@
+use crate::helpers::spawn_app;
+use std::time::Duration;
+use tokio::sync::watch;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_rt::test]
+async fn in_flight_requests_complete_during_a_graceful_shutdown(){
+    let mut app = spawn_app().await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // No idle connections: they would keep the server busy until they time out
+    let client = reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+    let subscription = tokio::spawn(
+        client
+            .post(format!("{}/subscriptions", app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+            .send()
+    );
+    // Wait for the handler to be busy talking to the email provider
+    while app.email_server.received_requests().await.unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    app.shut_down().await;
+
+    let response = subscription.await.unwrap().expect("The request was cut off");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(client.get(format!("{}/health_check", app.address)).send().await.is_err());
+}
+
+#[actix_rt::test]
+async fn the_delivery_worker_stops_when_asked(){
+    let app = spawn_app().await;
+    let (stop, stopped) = watch::channel(false);
+    let worker = tokio::spawn(app.delivery_worker.run_until_stopped(stopped));
+    // Give it time to find the queue empty and go to sleep
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    stop.send(true).unwrap();
+
+    let outcome = tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("The worker did not stop");
+    assert!(outcome.unwrap().is_ok());
+}
@@ -0,0 +1,160 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use secrecy::Secret;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{CaptchaProvider, CaptchaSettings};
+
+/// The value of the hidden `name` field of the subscribe form, as a browser would read it.
+fn hidden_field(html: &str, name: &str) -> String {
+    let marker = format!(r#"name="{}" value=""#, name);
+    let start = html.find(&marker).expect("The hidden field is missing") + marker.len();
+    let length = html[start..].find('"').unwrap();
+    htmlescape::decode_html(&html[start..start + length]).unwrap()
+}
+
+/// Load the subscribe page, then fill it in like a browser would.
+async fn fill_in_subscribe_page(app: &TestApp, name: &str, email: &str) -> reqwest::Response {
+    let html_page = app.get_subscribe_page_html().await;
+    app.post_subscribe_page(&serde_json::json!({
+        "name": name,
+        "email": email,
+        "website": "",
+        "form_token": hidden_field(&html_page, "form_token"),
+        "csrf_token": hidden_field(&html_page, "csrf_token")
+    }))
+        .await
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[actix_rt::test]
+async fn the_home_page_serves_the_subscribe_form() {
+    let app = spawn_app().await;
+
+    let response = app.api_client.get(&app.address).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/html");
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscribe" method="post">"#));
+    assert!(html_page.contains(r#"name="website""#));
+    assert!(!hidden_field(&html_page, "form_token").is_empty());
+    assert!(!hidden_field(&html_page, "csrf_token").is_empty());
+}
+
+#[actix_rt::test]
+async fn the_form_embeds_the_configured_captcha_widget() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.captcha = Some(CaptchaSettings {
+            provider: CaptchaProvider::HCaptcha,
+            site_key: "site-key".into(),
+            secret_key: Secret::new("captcha-secret".into()),
+            verify_url: None,
+            timeout_milliseconds: 1000
+        });
+    }).await;
+
+    let html_page = app.get_subscribe_page_html().await;
+
+    assert!(html_page.contains(r#"<script src="https://js.hcaptcha.com/1/api.js" async defer></script>"#));
+    assert!(html_page.contains(r#"<div class="h-captcha" data-sitekey="site&#x2D;key"></div>"#));
+}
+
+#[actix_rt::test]
+async fn subscribing_from_the_page_redirects_to_check_your_inbox() {
+    let app = spawn_app().await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = fill_in_subscribe_page(&app, "Atul Sharma", "asharma@sw-at.com").await;
+    assert_is_redirect_to(&response, "/subscriptions/check-your-inbox");
+
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/check-your-inbox", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Check your inbox"));
+    assert_eq!(subscriber_count(&app).await, 1);
+}
+
+#[actix_rt::test]
+async fn invalid_fields_are_reported_on_the_form() {
+    let app = spawn_app().await;
+
+    let response = fill_in_subscribe_page(&app, "Atul Sharma", "not-an-email").await;
+    assert_is_redirect_to(&response, "/");
+
+    let html_page = app.get_subscribe_page_html().await;
+    assert!(html_page.contains("<p><i>email: "));
+
+    // The message is gone once it has been displayed
+    let html_page = app.get_subscribe_page_html().await;
+    assert!(!html_page.contains("<p><i>"));
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[actix_rt::test]
+async fn submissions_without_a_matching_csrf_token_are_rejected() {
+    let app = spawn_app().await;
+    let html_page = app.get_subscribe_page_html().await;
+    let form_token = hidden_field(&html_page, "form_token");
+    let test_cases = vec![
+        (String::new(), "no CSRF token"),
+        ("a".repeat(32), "a CSRF token other than the cookie's"),
+    ];
+
+    for (csrf_token, description) in test_cases {
+        let response = app
+            .post_subscribe_page(&serde_json::json!({
+                "name": "Atul Sharma",
+                "email": "asharma@sw-at.com",
+                "form_token": &form_token,
+                "csrf_token": csrf_token
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 403, "Accepted {}", description);
+    }
+
+    // Nor is the token accepted without the cookie, as in a cross-site post
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscribe", &app.address))
+        .form(&serde_json::json!({
+            "name": "Atul Sharma",
+            "email": "asharma@sw-at.com",
+            "csrf_token": hidden_field(&html_page, "csrf_token")
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[actix_rt::test]
+async fn bot_checks_failing_on_the_page_are_reported_on_the_form() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.enabled = true;
+        c.bot_protection.min_submit_seconds = 60;
+    }).await;
+
+    // Submitted within a minute of loading the page
+    let response = fill_in_subscribe_page(&app, "Atul Sharma", "asharma@sw-at.com").await;
+    assert_is_redirect_to(&response, "/");
+
+    let html_page = app.get_subscribe_page_html().await;
+    assert!(html_page.contains("The form was submitted too quickly."));
+    assert_eq!(subscriber_count(&app).await, 0);
+}
@@ -1,8 +1,6 @@
 use crate::helpers::spawn_app;
-use reqwest::Url;
 use wiremock::{ResponseTemplate, Mock};
 use wiremock::matchers::{path, method};
-use validator::HasLen;
 
 #[actix_rt::test()]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -12,6 +10,7 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
         .unwrap();
 
     assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("This confirmation link is invalid"));
 }
 
 #[actix_rt::test]
@@ -26,25 +25,94 @@ async fn  the_link_returned_by_subscribe_returns_a_200_if_called() {
     app.post_subscription(body.into()).await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let confirmation_link = app.get_confirmation_links(email_request).plain_text;
 
-    let get_link = |s: &str| {
-        let links:Vec<_> = linkify::LinkFinder::new()
-            .links(s)
-            .filter(|l| *l.kind() == linkify::LinkKind::Url)
-            .collect();
-        assert_eq!(links.length(), 1);
-        links[0].as_str().to_owned()
-    };
+    let response = reqwest::get(confirmation_link)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Your subscription is confirmed"));
+}
+
+#[actix_rt::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let app = spawn_app().await;
+    let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscription(body.into()).await;
 
-    let raw_confirmation_link = &get_link(body.get("content").unwrap().get(0).unwrap().get("value").unwrap().as_str().unwrap());
-    let mut confirmation_link = Url::parse(raw_confirmation_link).unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
 
-    assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
-    confirmation_link.set_port(Some(app.port)).unwrap();
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
 
-    let response = reqwest::get(confirmation_link)
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions", )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "asharma@sw-at.com");
+    assert_eq!(saved.name, "Atul Sharma");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_rt::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknowntoken",
+        app.address
+    ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.text().await.unwrap().contains("This confirmation link is invalid"));
+}
+
+#[actix_rt::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("Subscribe again to request a new one"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_rt::test]
+async fn confirmation_links_do_not_opt_unsubscribed_subscribers_back_in() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
         .await
         .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
     assert_eq!(response.status().as_u16(), 200);
-}
\ No newline at end of file
+    assert!(response.text().await.unwrap().contains("already been used"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+}
@@ -1,7 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{captured_logs, spawn_app};
+use std::time::Duration;
+use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
-use validator::HasLen;
 
 
 #[actix_rt::test]
@@ -23,6 +24,11 @@ async fn subscribe_returns_a_200_form_valid_form_data() {
 async fn subscribe_persists_the_nw_susbcriber() {
     let app = spawn_app().await;
     let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
 
     app.post_subscription(body.into()).await;
     let saved = sqlx::query!("SELECT email, name, status FROM subscriptions", )
@@ -34,6 +40,63 @@ async fn subscribe_persists_the_nw_susbcriber() {
     assert_eq!(saved.status, "pending_confirmation");
 }
 
+#[actix_rt::test]
+async fn subscribers_are_not_stored_if_their_token_cannot_be() {
+    let app = spawn_app().await;
+    let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscription(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions");
+    assert_eq!(saved.count, 0);
+}
+
+#[actix_rt::test]
+async fn rejected_fields_are_never_logged() {
+    let app = spawn_app().await;
+    let marker = Uuid::new_v4().to_string();
+    let body = format!("name=%3C{}%3E&email=bad-email-{}", marker, marker);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        // Only to find this request in the logs
+        .header("User-Agent", format!("agent-{}", marker))
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The request span is logged with its error once it closes
+    let agent = format!("\"http.user_agent\":\"agent-{}\"", marker);
+    let logged = |logs: &str| logs.lines().any(|line| line.contains(&agent) && line.contains("exception.message"));
+    let mut logs = captured_logs();
+    for _ in 0..50 {
+        if logged(&logs) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        logs = captured_logs();
+    }
+    assert!(logged(&logs), "The rejected request was not logged");
+    // The marker may only show up as part of the user agent
+    let leaks: Vec<_> = logs
+        .lines()
+        .filter(|line| line.matches(marker.as_str()).count() > line.matches(agent.as_str()).count())
+        .collect();
+    assert!(leaks.is_empty(), "Rejected fields were logged: {:?}", leaks);
+}
+
 #[actix_rt::test]
 async fn subscribe_returns_a_400_when_data_is_missing() {
     let app = spawn_app().await;
@@ -74,6 +137,28 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_empty() {
     }
 }
 
+#[actix_rt::test]
+async fn subscribe_returns_problem_details_naming_every_invalid_field() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscription("name=&email=bad-email-address".into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        "application/problem+json",
+        response.headers()["Content-Type"].to_str().unwrap()
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    let fields: Vec<_> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["name", "email"]);
+}
+
 #[actix_rt::test]
 async fn  subscribe_sends_a_confirmation_email_for_valid_data() {
     let app = spawn_app().await;
@@ -98,20 +183,184 @@ async fn  subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
     app.post_subscription(body.into()).await;
 
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[actix_rt::test]
+async fn confirmation_emails_greet_the_subscriber_and_carry_an_unsubscribe_link() {
+    let app = spawn_app().await;
+    let body ="name=Tom%20%26%20Jerry&email=asharma%40sw-at.com";
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscription(body.into()).await;
+
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let plain_text = body["content"][0]["value"].as_str().unwrap();
+    let html = body["content"][1]["value"].as_str().unwrap();
 
-    let get_link = |s: &str| {
-        let links:Vec<_> = linkify::LinkFinder::new()
-            .links(s)
-            .filter(|l| *l.kind() == linkify::LinkKind::Url)
-            .collect();
-        assert_eq!(links.length(), 1);
-        links[0].as_str().to_owned()
-    };
+    assert!(plain_text.contains("Hi Tom & Jerry"));
+    assert!(html.contains("Hi Tom &amp; Jerry"));
+    assert!(plain_text.contains("/subscriptions/unsubscribe?token="));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+}
 
-    let text_link = get_link(body.get("content").unwrap().get(0).unwrap().get("value").unwrap().as_str().unwrap());
-    let html_link = get_link(body.get("content").unwrap().get(1).unwrap().get("value").unwrap().as_str().unwrap());
+#[actix_rt::test]
+async fn subscribe_is_idempotent() {
+    let app = spawn_app().await;
+    let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
 
-    assert_eq!(text_link, html_link);
-}
\ No newline at end of file
+    let first = app.post_subscription_with_idempotency_key(body.into(), &idempotency_key).await;
+    let second = app.post_subscription_with_idempotency_key(body.into(), &idempotency_key).await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+}
+
+#[actix_rt::test]
+async fn concurrent_subscriptions_with_the_same_idempotency_key_are_handled_once() {
+    let app = spawn_app().await;
+    let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscription_with_idempotency_key(body.into(), &idempotency_key),
+        app.post_subscription_with_idempotency_key(body.into(), &idempotency_key)
+    );
+
+    assert_eq!(first.status(), second.status());
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+}
+
+#[actix_rt::test]
+async fn subscribe_returns_a_400_for_an_invalid_idempotency_key() {
+    let app = spawn_app().await;
+    let body ="name=Atul%20Sharma&email=asharma%40sw-at.com";
+
+    let response = app.post_subscription_with_idempotency_key(body.into(), &"a".repeat(50)).await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["errors"][0]["field"], "Idempotency-Key");
+}
+
+#[actix_rt::test]
+async fn an_idempotency_key_reused_for_another_subscription_is_rejected() {
+    let app = spawn_app().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscription_with_idempotency_key(
+        "name=Atul%20Sharma&email=asharma%40sw-at.com".into(),
+        &idempotency_key
+    ).await;
+    let second = app.post_subscription_with_idempotency_key(
+        "name=Ursula&email=ursula_le_guin%40gmail.com".into(),
+        &idempotency_key
+    ).await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(422, second.status().as_u16());
+    assert_eq!(
+        second.headers()["Content-Type"].to_str().unwrap(),
+        "application/problem+json"
+    );
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch subscriptions.");
+    assert_eq!(saved.len(), 1);
+}
+
+#[actix_rt::test]
+async fn subscribing_again_while_pending_resends_a_working_confirmation_link() {
+    let app = spawn_app().await;
+    let first_link = app.create_unconfirmed_subscriber().await;
+
+    let second_link = app.create_unconfirmed_subscriber().await;
+
+    let response = reqwest::get(first_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(second_link.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_rt::test]
+async fn subscribing_again_once_confirmed_returns_a_200_and_sends_nothing() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscription("name=Atul%20Sharma&email=asharma%40sw-at.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[actix_rt::test]
+async fn unsubscribed_subscribers_can_opt_back_in() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let confirmation_link = app.create_unconfirmed_subscriber().await;
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "confirmed");
+}
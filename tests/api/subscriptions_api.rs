@@ -0,0 +1,130 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[actix_rt::test]
+async fn subscribe_json_accepts_a_new_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscription_json(&serde_json::json!({
+            "name": "Atul Sharma",
+            "email": "asharma@sw-at.com"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    assert!(uuid::Uuid::parse_str(body["id"].as_str().unwrap()).is_ok());
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "asharma@sw-at.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_rt::test]
+async fn subscribe_json_reports_every_invalid_or_missing_field() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!({"name": "", "email": "bad-email-address"}), vec!["name", "email"]),
+        (serde_json::json!({"name": "Atul Sharma"}), vec!["email"]),
+        (serde_json::json!({}), vec!["name", "email"]),
+    ];
+
+    for (invalid_body, expected_fields) in test_cases {
+        let response = app.post_subscription_json(&invalid_body).await;
+
+        assert_eq!(response.status().as_u16(), 400, "Payload: {}", invalid_body);
+        assert_eq!(
+            response.headers()["Content-Type"].to_str().unwrap(),
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        let fields: Vec<_> = problem["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, expected_fields, "Payload: {}", invalid_body);
+    }
+}
+
+#[actix_rt::test]
+async fn subscribe_json_rejects_malformed_bodies_with_problem_details() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body("{\"name\": ")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "application/problem+json"
+    );
+}
+
+#[actix_rt::test]
+async fn subscribe_json_only_accepts_json() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=Atul%20Sharma&email=asharma%40sw-at.com")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 415);
+}
+
+#[actix_rt::test]
+async fn subscribe_json_does_not_tell_confirmed_subscribers_apart() {
+    let new_subscriber_app = spawn_app().await;
+    Mock::given(path("/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&new_subscriber_app.email_server)
+        .await;
+    let new_subscriber_body: serde_json::Value = new_subscriber_app
+        .post_subscription_json(&serde_json::json!({
+            "name": "Atul Sharma",
+            "email": "asharma@sw-at.com"
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/mail/send"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscription_json(&serde_json::json!({
+            "name": "Atul Sharma",
+            "email": "asharma@sw-at.com"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, new_subscriber_body);
+}
@@ -0,0 +1,153 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletters::newsletter_request_body;
+use reqwest::Url;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue to the (only) confirmed subscriber and return the
+/// headers attached to the email that was sent.
+async fn deliver_issue(app: &TestApp) -> serde_json::Value {
+    let _mock_guard = Mock::given(path("/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["headers"].clone()
+}
+
+fn unsubscribe_link(app: &TestApp, headers: &serde_json::Value) -> Url {
+    let raw_link = headers["List-Unsubscribe"]
+        .as_str()
+        .unwrap()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    let mut link = Url::parse(raw_link).unwrap();
+    assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status
+}
+
+#[actix_rt::test]
+async fn newsletter_emails_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+
+    let headers = deliver_issue(&app).await;
+
+    assert_eq!(headers["List-Unsubscribe-Post"], "List-Unsubscribe=One-Click");
+    let link = unsubscribe_link(&app, &headers);
+    assert_eq!(link.path(), "/subscriptions/unsubscribe");
+}
+
+#[actix_rt::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let link = unsubscribe_link(&app, &deliver_issue(&app).await);
+
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[actix_rt::test]
+async fn visiting_the_unsubscribe_link_does_not_unsubscribe() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let link = unsubscribe_link(&app, &deliver_issue(&app).await);
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[actix_rt::test]
+async fn unsubscribe_requests_with_a_forged_token_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token={}.forged",
+            app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[actix_rt::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let link = unsubscribe_link(&app, &deliver_issue(&app).await);
+    reqwest::Client::new()
+        .post(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_request_body()).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_rt::test]
+async fn unsubscribing_invalidates_pending_confirmation_links() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    // The confirmation email carries an unsubscribe link in its footer
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let raw_link = linkify::LinkFinder::new()
+        .links(body["content"][0]["value"].as_str().unwrap())
+        .find(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+        .unwrap()
+        .as_str()
+        .to_owned();
+    let mut link = Url::parse(&raw_link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    let response = reqwest::Client::new().post(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}